@@ -36,24 +36,29 @@
 #![allow(clippy::needless_doctest_main)]
 #![deny(missing_docs, missing_debug_implementations)]
 
+use std::borrow::Cow;
 use std::cmp::{max, min};
-use std::collections::VecDeque;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
 use std::error;
 use std::fmt;
+use std::hash::{BuildHasher, Hasher};
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
-use std::sync::{Arc, Weak};
+use std::panic::Location;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use futures::channel::oneshot;
-use futures::future::ok;
+use futures::future::{BoxFuture, Either, Shared};
 use futures::prelude::*;
 use futures::stream::FuturesUnordered;
+use tokio::runtime::Handle;
 use tokio::spawn;
-use tokio::sync::{Mutex, MutexGuard};
-use tokio::time::{delay_for, interval_at, timeout, Interval};
+use tokio::time::{delay_for, interval_at, timeout, timeout_at, Interval};
 
 /// A trait which provides connection-specific functionality.
 #[async_trait]
@@ -69,6 +74,58 @@ pub trait ManageConnection: Send + Sync + 'static {
     async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error>;
     /// Synchronously determine if the connection is no longer usable, if possible.
     fn has_broken(&self, conn: &mut Self::Connection) -> bool;
+    /// Determines whether an error returned by `connect` is worth retrying.
+    ///
+    /// Errors which can't be fixed by retrying, like invalid credentials, are
+    /// reported immediately instead of after `Builder::connect_retry_budget`.
+    ///
+    /// Defaults to treating every error as retryable.
+    fn is_retryable(&self, _error: &Self::Error) -> bool {
+        true
+    }
+    /// Prepares a connection that has been returned to the pool for reuse,
+    /// e.g. by resetting session state.
+    ///
+    /// This only runs if `Builder::test_on_check_in` is set. Connections for
+    /// which it fails are closed.
+    ///
+    /// Defaults to doing nothing.
+    async fn recycle(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Closes a connection which the pool is discarding, e.g. because it
+    /// broke, expired or the pool itself is being dropped.
+    ///
+    /// This gives protocols with a goodbye message a chance to send it. The
+    /// pool runs it on a background task if it doesn't complete immediately.
+    ///
+    /// Defaults to dropping the connection.
+    async fn close(&self, _conn: Self::Connection) {}
+}
+
+/// A trait which provides functionality to customize connections as they
+/// move in and out of the pool.
+///
+/// An error returned from any of the hooks causes the connection to be
+/// discarded.
+#[async_trait]
+pub trait CustomizeConnection<C: Send + 'static, E: 'static>:
+    fmt::Debug + Send + Sync + 'static
+{
+    /// Called with connections immediately after they are established.
+    async fn on_connect(&self, _conn: &mut C) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Called with connections before they are handed out to a pool user.
+    async fn on_acquire(&self, _conn: &mut C) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Called with connections when they are returned to the pool.
+    async fn on_release(&self, _conn: &mut C) -> Result<(), E> {
+        Ok(())
+    }
 }
 
 /// bb8's error type.
@@ -77,7 +134,15 @@ pub enum RunError<E> {
     /// An error returned from user code.
     User(E),
     /// bb8 attempted to get a connection but the provided timeout was exceeded.
-    TimedOut,
+    TimedOut(TimeoutDiagnostics),
+    /// bb8 attempted to get a connection but the pool has been closed.
+    PoolClosed,
+    /// bb8 was asked for an idle connection without waiting, but there were
+    /// none.
+    NoIdleConnections,
+    /// bb8 did not attempt to get a connection because `Builder::max_waiters`
+    /// callers were already waiting for one.
+    TooManyWaiters,
 }
 
 impl<E> fmt::Display for RunError<E>
@@ -87,7 +152,25 @@ where
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             RunError::User(ref err) => write!(f, "{}", err),
-            RunError::TimedOut => write!(f, "Timed out in bb8"),
+            RunError::TimedOut(ref diagnostics) => {
+                write!(f, "Timed out in bb8 after {:?}", diagnostics.waited)?;
+                let state = &diagnostics.state;
+                write!(
+                    f,
+                    " ({} connections, {} idle, {} pending, {} waiters)",
+                    state.connections,
+                    state.idle_connections,
+                    state.pending_connections,
+                    state.waiters
+                )?;
+                if let Some(ref err) = diagnostics.last_connect_error {
+                    write!(f, "; last connect error: {}", err)?;
+                }
+                Ok(())
+            }
+            RunError::PoolClosed => write!(f, "The bb8 pool has been closed"),
+            RunError::NoIdleConnections => write!(f, "No idle connections in bb8"),
+            RunError::TooManyWaiters => write!(f, "Too many callers waiting in bb8"),
         }
     }
 }
@@ -99,7 +182,10 @@ where
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
             RunError::User(ref err) => Some(err),
-            RunError::TimedOut => None,
+            RunError::TimedOut(_)
+            | RunError::PoolClosed
+            | RunError::NoIdleConnections
+            | RunError::TooManyWaiters => None,
         }
     }
 }
@@ -113,16 +199,57 @@ where
     }
 }
 
+/// Details about a checkout which timed out, to help tell a saturated pool
+/// apart from a database which can't be reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TimeoutDiagnostics {
+    /// How long the caller waited before giving up.
+    pub waited: Duration,
+    /// The state of the pool when the caller gave up.
+    pub state: State,
+    /// The error returned by `ManageConnection::connect` the last time it
+    /// failed, unless a connection has been established since.
+    pub last_connect_error: Option<String>,
+}
+
 /// A trait to receive errors generated by connection management that aren't
 /// tied to any particular caller.
 pub trait ErrorSink<E>: fmt::Debug + Send + Sync + 'static {
     /// Receive an error
     fn sink(&self, error: E);
 
+    /// Receive a report about a connection which has been checked out for
+    /// longer than `Builder::leak_detection_threshold`.
+    ///
+    /// Defaults to doing nothing.
+    fn sink_leak(&self, _leak: ConnectionLeak) {}
+
     /// Clone this sink.
     fn boxed_clone(&self) -> Box<dyn ErrorSink<E>>;
 }
 
+/// A connection which may have been leaked, because it has been checked out
+/// for longer than `Builder::leak_detection_threshold`.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ConnectionLeak {
+    /// Where the connection was checked out.
+    pub location: &'static Location<'static>,
+    /// How long the connection had been checked out when it was reported.
+    pub held_for: Duration,
+}
+
+impl fmt::Display for ConnectionLeak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Connection checked out at {} has been held for {:?}",
+            self.location, self.held_for
+        )
+    }
+}
+
 /// An `ErrorSink` implementation that does nothing.
 #[derive(Debug, Clone, Copy)]
 pub struct NopErrorSink;
@@ -135,13 +262,159 @@ impl<E> ErrorSink<E> for NopErrorSink {
     }
 }
 
+/// A policy deciding how long to wait between attempts to establish a
+/// connection.
+pub trait Backoff: fmt::Debug + Send + Sync + 'static {
+    /// Returns how long to wait after the given number of failed attempts, or
+    /// `None` to stop retrying.
+    ///
+    /// `attempts` starts at 1 after the first failure.
+    fn next_delay(&self, attempts: u32) -> Option<Duration>;
+}
+
+/// A `Backoff` implementation which doubles the delay after every failed
+/// attempt.
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    base: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    max_attempts: Option<u32>,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        ExponentialBackoff {
+            base: Duration::from_millis(200),
+            max_delay: Duration::from_secs(15),
+            jitter: false,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// Constructs a new `ExponentialBackoff`.
+    ///
+    /// The first retry waits twice the `base` delay.
+    pub fn new(base: Duration) -> ExponentialBackoff {
+        ExponentialBackoff {
+            base,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the maximum delay between two attempts.
+    ///
+    /// Defaults to 15 seconds.
+    pub fn max_delay(mut self, max_delay: Duration) -> ExponentialBackoff {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// If true, every delay is replaced by a random duration between zero and
+    /// that delay, so that many pools don't retry in lockstep.
+    ///
+    /// Defaults to false.
+    pub fn jitter(mut self, jitter: bool) -> ExponentialBackoff {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the maximum number of attempts, if any, before giving up.
+    ///
+    /// Defaults to None.
+    pub fn max_attempts(mut self, max_attempts: Option<u32>) -> ExponentialBackoff {
+        assert!(
+            max_attempts != Some(0),
+            "max_attempts must be greater than zero!"
+        );
+        self.max_attempts = max_attempts;
+        self
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn next_delay(&self, attempts: u32) -> Option<Duration> {
+        if let Some(max_attempts) = self.max_attempts {
+            if attempts >= max_attempts {
+                return None;
+            }
+        }
+
+        let factor = 1u32.checked_shl(attempts).unwrap_or(u32::MAX);
+        let delay = min(
+            self.base.checked_mul(factor).unwrap_or(self.max_delay),
+            self.max_delay,
+        );
+        if self.jitter {
+            Some(delay.mul_f64(random_fraction()))
+        } else {
+            Some(delay)
+        }
+    }
+}
+
+/// Returns a random number in `[0, 1)`, without pulling in an RNG crate.
+fn random_fraction() -> f64 {
+    // Every `RandomState` is seeded differently.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A policy deciding which errors `Pool::run_with_retry` retries, and how
+/// long to wait in between.
+pub trait RetryPolicy<E>: Send + Sync {
+    /// Determines whether an error is transient, e.g. caused by a failover,
+    /// and worth retrying on a fresh connection.
+    fn is_transient(&self, error: &E) -> bool;
+    /// Returns the policy used to space out attempts, and to decide when to
+    /// stop retrying, e.g. an `ExponentialBackoff`.
+    fn backoff(&self) -> &dyn Backoff;
+}
+
+/// The order in which idle connections are handed out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueStrategy {
+    /// Hand out the connection that has been idle the longest, cycling
+    /// through every idle connection.
+    #[default]
+    Fifo,
+    /// Hand out the connection that was returned most recently, so that a
+    /// small set of connections stays busy and the rest can age out through
+    /// `Builder::idle_timeout`.
+    Lifo,
+}
+
+/// The priority of a request for a connection.
+///
+/// When a connection becomes available, it goes to the waiter with the highest
+/// priority. To avoid starving lower priority requests, waiters gain urgency
+/// as they wait; see `Builder::priority_aging`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// For background work which can afford to wait.
+    Low,
+    /// The priority used by `Pool::get`.
+    #[default]
+    Normal,
+    /// For latency-sensitive work.
+    High,
+}
+
 /// Information about the state of a `Pool`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct State {
     /// The number of connections currently being managed by the pool.
     pub connections: u32,
     /// The number of idle connections.
     pub idle_connections: u32,
-    _p: (),
+    /// The number of connections currently being established.
+    pub pending_connections: u32,
+    /// The number of callers waiting for a connection.
+    pub waiters: u32,
 }
 
 impl fmt::Debug for State {
@@ -149,10 +422,131 @@ impl fmt::Debug for State {
         fmt.debug_struct("State")
             .field("connections", &self.connections)
             .field("idle_connections", &self.idle_connections)
+            .field("pending_connections", &self.pending_connections)
+            .field("waiters", &self.waiters)
             .finish()
     }
 }
 
+/// Gauges mirroring `PoolInternals`, so that `Pool::state` can read them
+/// without taking the pool lock.
+#[derive(Debug, Default)]
+struct AtomicState {
+    connections: AtomicU32,
+    idle_connections: AtomicU32,
+    pending_connections: AtomicU32,
+    waiters: AtomicU32,
+}
+
+impl AtomicState {
+    fn store(&self, state: State) {
+        self.connections.store(state.connections, Ordering::Relaxed);
+        self.idle_connections
+            .store(state.idle_connections, Ordering::Relaxed);
+        self.pending_connections
+            .store(state.pending_connections, Ordering::Relaxed);
+        self.waiters.store(state.waiters, Ordering::Relaxed);
+    }
+
+    fn load(&self) -> State {
+        State {
+            connections: self.connections.load(Ordering::Relaxed),
+            idle_connections: self.idle_connections.load(Ordering::Relaxed),
+            pending_connections: self.pending_connections.load(Ordering::Relaxed),
+            waiters: self.waiters.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Cumulative statistics about a `Pool`.
+///
+/// All counters only ever increase over the lifetime of the pool.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Statistics {
+    /// The number of checkouts served immediately by an idle connection.
+    pub get_direct: u64,
+    /// The number of checkouts that had to wait for a connection.
+    pub get_waited: u64,
+    /// The number of checkouts that failed with `RunError::TimedOut`.
+    pub get_timed_out: u64,
+    /// The number of checkouts that failed with `RunError::TooManyWaiters`.
+    pub get_rejected: u64,
+    /// The total time spent by checkouts that had to wait for a connection.
+    pub get_wait_time: Duration,
+    /// The number of connections established by the pool.
+    pub connections_created: u64,
+    /// The number of failed attempts to establish a connection.
+    pub connections_failed: u64,
+    /// The number of connections closed for exceeding `idle_timeout`.
+    pub connections_closed_idle_timeout: u64,
+    /// The number of connections closed for exceeding `max_lifetime`.
+    pub connections_closed_max_lifetime: u64,
+    /// The number of connections closed for reaching `max_uses`.
+    pub connections_closed_max_uses: u64,
+    /// The number of connections rejected by `ManageConnection::is_valid`.
+    pub connections_closed_invalid: u64,
+    /// The number of connections rejected by `ManageConnection::has_broken`, or
+    /// found to be broken by their user.
+    pub connections_closed_broken: u64,
+    /// The number of connections rejected by `ManageConnection::recycle`.
+    pub connections_closed_recycle: u64,
+    /// The number of connections closed by `Pool::run_with_retry` after a
+    /// transient error.
+    pub connections_closed_retried: u64,
+}
+
+#[derive(Debug, Default)]
+struct AtomicStatistics {
+    get_direct: AtomicU64,
+    get_waited: AtomicU64,
+    get_timed_out: AtomicU64,
+    get_rejected: AtomicU64,
+    get_wait_time_micros: AtomicU64,
+    connections_created: AtomicU64,
+    connections_failed: AtomicU64,
+    connections_closed_idle_timeout: AtomicU64,
+    connections_closed_max_lifetime: AtomicU64,
+    connections_closed_max_uses: AtomicU64,
+    connections_closed_invalid: AtomicU64,
+    connections_closed_broken: AtomicU64,
+    connections_closed_recycle: AtomicU64,
+    connections_closed_retried: AtomicU64,
+}
+
+impl AtomicStatistics {
+    fn record(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn record_wait(&self, waited: Duration) {
+        Self::record(&self.get_wait_time_micros, waited.as_micros() as u64);
+    }
+
+    fn load(&self) -> Statistics {
+        Statistics {
+            get_direct: self.get_direct.load(Ordering::Relaxed),
+            get_waited: self.get_waited.load(Ordering::Relaxed),
+            get_timed_out: self.get_timed_out.load(Ordering::Relaxed),
+            get_rejected: self.get_rejected.load(Ordering::Relaxed),
+            get_wait_time: Duration::from_micros(self.get_wait_time_micros.load(Ordering::Relaxed)),
+            connections_created: self.connections_created.load(Ordering::Relaxed),
+            connections_failed: self.connections_failed.load(Ordering::Relaxed),
+            connections_closed_idle_timeout: self
+                .connections_closed_idle_timeout
+                .load(Ordering::Relaxed),
+            connections_closed_max_lifetime: self
+                .connections_closed_max_lifetime
+                .load(Ordering::Relaxed),
+            connections_closed_max_uses: self.connections_closed_max_uses.load(Ordering::Relaxed),
+            connections_closed_invalid: self.connections_closed_invalid.load(Ordering::Relaxed),
+            connections_closed_broken: self.connections_closed_broken.load(Ordering::Relaxed),
+            connections_closed_recycle: self.connections_closed_recycle.load(Ordering::Relaxed),
+            connections_closed_retried: self.connections_closed_retried.load(Ordering::Relaxed),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Conn<C>
 where
@@ -160,6 +554,7 @@ where
 {
     conn: C,
     birth: Instant,
+    uses: u64,
 }
 
 struct IdleConn<C>
@@ -168,6 +563,9 @@ where
 {
     conn: Conn<C>,
     idle_start: Instant,
+    /// When the connection was last known to be healthy, i.e. when it was
+    /// returned to the pool or last passed a background health check.
+    last_checked: Instant,
 }
 
 impl<C> IdleConn<C>
@@ -179,6 +577,7 @@ where
         IdleConn {
             conn,
             idle_start: now,
+            last_checked: now,
         }
     }
 }
@@ -192,14 +591,40 @@ pub struct Builder<M: ManageConnection> {
     min_idle: Option<u32>,
     /// Whether or not to test the connection on checkout.
     test_on_check_out: bool,
+    /// How long a connection has to be idle before it is validated on checkout.
+    validation_threshold: Duration,
+    /// Whether or not to recycle the connection on checkin.
+    test_on_check_in: bool,
     /// The maximum lifetime, if any, that a connection is allowed.
     max_lifetime: Option<Duration>,
+    /// The maximum number of times, if any, that a connection may be checked out.
+    max_uses: Option<u64>,
     /// The duration, if any, after which idle_connections in excess of `min_idle` are closed.
     idle_timeout: Option<Duration>,
-    /// The duration to wait to start a connection before giving up.
-    connection_timeout: Duration,
+    /// The duration to wait for a connection to be checked out before giving up.
+    checkout_timeout: Duration,
+    /// The duration, if any, to wait for a single connection attempt to finish.
+    connect_timeout: Option<Duration>,
+    /// The duration to keep retrying to establish a connection before giving up.
+    connect_retry_budget: Duration,
+    /// The policy used to space out attempts to establish a connection.
+    backoff: Box<dyn Backoff>,
+    /// How long a waiter has to wait to gain one level of priority.
+    priority_aging: Duration,
+    /// The maximum number of callers waiting for a connection.
+    max_waiters: Option<usize>,
+    /// The order in which idle connections are handed out.
+    queue_strategy: QueueStrategy,
+    /// How often idle connections are validated in the background.
+    idle_health_check_interval: Option<Duration>,
+    /// The duration to wait for a background health check to pass.
+    idle_health_check_timeout: Duration,
+    /// How long a connection may be checked out before it is reported as leaked.
+    leak_detection_threshold: Option<Duration>,
     /// The error sink.
     error_sink: Box<dyn ErrorSink<M::Error>>,
+    /// The connection customizer, if any.
+    connection_customizer: Option<Box<dyn CustomizeConnection<M::Connection, M::Error>>>,
     /// The time interval used to wake up and reap connections.
     reaper_rate: Duration,
     _p: PhantomData<M>,
@@ -211,10 +636,23 @@ impl<M: ManageConnection> Default for Builder<M> {
             max_size: 10,
             min_idle: None,
             test_on_check_out: true,
+            validation_threshold: Duration::from_secs(0),
+            test_on_check_in: false,
             max_lifetime: Some(Duration::from_secs(30 * 60)),
+            max_uses: None,
             idle_timeout: Some(Duration::from_secs(10 * 60)),
-            connection_timeout: Duration::from_secs(30),
+            checkout_timeout: Duration::from_secs(30),
+            connect_timeout: None,
+            connect_retry_budget: Duration::from_secs(30),
+            backoff: Box::new(ExponentialBackoff::default()),
+            priority_aging: Duration::from_secs(1),
+            max_waiters: None,
+            queue_strategy: QueueStrategy::Fifo,
+            idle_health_check_interval: None,
+            idle_health_check_timeout: Duration::from_secs(5),
+            leak_detection_threshold: None,
             error_sink: Box::new(NopErrorSink),
+            connection_customizer: None,
             reaper_rate: Duration::from_secs(30),
             _p: PhantomData,
         }
@@ -258,6 +696,28 @@ impl<M: ManageConnection> Builder<M> {
         self
     }
 
+    /// If true, connections returned to the pool are passed through
+    /// `ManageConnection::recycle` before they are handed out again, and
+    /// closed if that fails.
+    ///
+    /// Defaults to false.
+    pub fn test_on_check_in(mut self, test_on_check_in: bool) -> Builder<M> {
+        self.test_on_check_in = test_on_check_in;
+        self
+    }
+
+    /// Sets how long a connection has to sit idle before `test_on_check_out`
+    /// validates it.
+    ///
+    /// Connections which were returned to the pool more recently than this are
+    /// assumed to be healthy, which saves a round trip on busy pools.
+    ///
+    /// Defaults to 0, which validates connections on every checkout.
+    pub fn validation_threshold(mut self, validation_threshold: Duration) -> Builder<M> {
+        self.validation_threshold = validation_threshold;
+        self
+    }
+
     /// Sets the maximum lifetime of connections in the pool.
     ///
     /// If set, connections will be closed at the next reaping after surviving
@@ -276,6 +736,18 @@ impl<M: ManageConnection> Builder<M> {
         self
     }
 
+    /// Sets the maximum number of times a connection may be checked out.
+    ///
+    /// If set, connections will be closed when they are returned to the pool
+    /// after being checked out this many times.
+    ///
+    /// Defaults to None.
+    pub fn max_uses(mut self, max_uses: Option<u64>) -> Builder<M> {
+        assert!(max_uses != Some(0), "max_uses must be greater than zero!");
+        self.max_uses = max_uses;
+        self
+    }
+
     /// Sets the idle timeout used by the pool.
     ///
     /// If set, idle connections in excess of `min_idle` will be closed at the
@@ -293,16 +765,162 @@ impl<M: ManageConnection> Builder<M> {
 
     /// Sets the connection timeout used by the pool.
     ///
+    /// This is a shorthand for setting both `checkout_timeout` and
+    /// `connect_retry_budget` to the same duration.
+    pub fn connection_timeout(self, connection_timeout: Duration) -> Builder<M> {
+        assert!(
+            connection_timeout > Duration::from_secs(0),
+            "connection_timeout must be non-zero"
+        );
+        self.checkout_timeout(connection_timeout)
+            .connect_retry_budget(connection_timeout)
+    }
+
+    /// Sets the checkout timeout used by the pool.
+    ///
     /// Futures returned by `Pool::get` will wait this long before giving up and
     /// resolving with an error.
     ///
     /// Defaults to 30 seconds.
-    pub fn connection_timeout(mut self, connection_timeout: Duration) -> Builder<M> {
+    pub fn checkout_timeout(mut self, checkout_timeout: Duration) -> Builder<M> {
         assert!(
-            connection_timeout > Duration::from_secs(0),
-            "connection_timeout must be non-zero"
+            checkout_timeout > Duration::from_secs(0),
+            "checkout_timeout must be non-zero"
+        );
+        self.checkout_timeout = checkout_timeout;
+        self
+    }
+
+    /// Sets the timeout for a single call to `ManageConnection::connect`.
+    ///
+    /// Attempts which take longer than this are abandoned and retried, as if
+    /// they had failed. Regardless of this setting, no attempt is allowed to
+    /// outlast the `connect_retry_budget`.
+    ///
+    /// Defaults to None.
+    pub fn connect_timeout(mut self, connect_timeout: Option<Duration>) -> Builder<M> {
+        assert!(
+            connect_timeout != Some(Duration::from_secs(0)),
+            "connect_timeout must be greater than zero!"
+        );
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Sets how long the pool keeps retrying to establish a new connection
+    /// before giving up.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn connect_retry_budget(mut self, connect_retry_budget: Duration) -> Builder<M> {
+        assert!(
+            connect_retry_budget > Duration::from_secs(0),
+            "connect_retry_budget must be non-zero"
+        );
+        self.connect_retry_budget = connect_retry_budget;
+        self
+    }
+
+    /// Sets the policy used to space out attempts to establish a connection.
+    ///
+    /// Defaults to an `ExponentialBackoff` without jitter.
+    pub fn backoff(mut self, backoff: Box<dyn Backoff>) -> Builder<M> {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets how long a caller waiting for a connection has to wait to be
+    /// treated as if it had asked with the next higher `Priority`.
+    ///
+    /// For example, a `Priority::Low` waiter which has waited for twice this
+    /// duration is served before a `Priority::High` waiter which just arrived.
+    ///
+    /// Defaults to 1 second.
+    pub fn priority_aging(mut self, priority_aging: Duration) -> Builder<M> {
+        self.priority_aging = priority_aging;
+        self
+    }
+
+    /// Sets the maximum number of callers that may wait for a connection at
+    /// once.
+    ///
+    /// Once the limit is reached, further attempts to get a connection fail
+    /// immediately with `RunError::TooManyWaiters` instead of queueing up.
+    ///
+    /// Defaults to None.
+    pub fn max_waiters(mut self, max_waiters: Option<usize>) -> Builder<M> {
+        assert!(
+            max_waiters != Some(0),
+            "max_waiters must be greater than zero!"
+        );
+        self.max_waiters = max_waiters;
+        self
+    }
+
+    /// Sets the order in which idle connections are handed out.
+    ///
+    /// Defaults to `QueueStrategy::Fifo`.
+    pub fn queue_strategy(mut self, queue_strategy: QueueStrategy) -> Builder<M> {
+        self.queue_strategy = queue_strategy;
+        self
+    }
+
+    /// Sets how often idle connections are validated in the background.
+    ///
+    /// If set, connections which have been idle for at least this long are
+    /// periodically checked with `ManageConnection::is_valid`, a few at a
+    /// time so that the others remain available in the meantime. Connections
+    /// which fail the check, or don't pass it within
+    /// `idle_health_check_timeout`, are closed and replaced as needed to
+    /// maintain `min_idle`. This also serves as a keepalive for otherwise
+    /// quiet connections.
+    ///
+    /// Defaults to None.
+    pub fn idle_health_check_interval(
+        mut self,
+        idle_health_check_interval: Option<Duration>,
+    ) -> Builder<M> {
+        assert!(
+            idle_health_check_interval != Some(Duration::from_secs(0)),
+            "idle_health_check_interval must be greater than zero!"
+        );
+        self.idle_health_check_interval = idle_health_check_interval;
+        self
+    }
+
+    /// Sets how long a background health check may take before the
+    /// connection is considered broken.
+    ///
+    /// Connections are out of the idle queue while they are being checked,
+    /// so this should be short.
+    ///
+    /// Defaults to 5 seconds.
+    pub fn idle_health_check_timeout(mut self, idle_health_check_timeout: Duration) -> Builder<M> {
+        assert!(
+            idle_health_check_timeout > Duration::from_secs(0),
+            "idle_health_check_timeout must be non-zero"
+        );
+        self.idle_health_check_timeout = idle_health_check_timeout;
+        self
+    }
+
+    /// Sets how long a `PooledConnection` may be held before it is reported
+    /// as a possible leak.
+    ///
+    /// If set, connections checked out through `Pool::get` and friends which
+    /// are held for longer than this are reported to
+    /// `ErrorSink::sink_leak`, along with where they were checked out. Each
+    /// checkout is reported at most once.
+    ///
+    /// Defaults to None.
+    pub fn leak_detection_threshold(
+        mut self,
+        leak_detection_threshold: Option<Duration>,
+    ) -> Builder<M> {
+        assert!(
+            leak_detection_threshold != Some(Duration::from_secs(0)),
+            "leak_detection_threshold must be greater than zero!"
         );
-        self.connection_timeout = connection_timeout;
+        self.leak_detection_threshold = leak_detection_threshold;
         self
     }
 
@@ -315,6 +933,18 @@ impl<M: ManageConnection> Builder<M> {
         self
     }
 
+    /// Set the connection customizer, whose hooks run as connections are
+    /// established, checked out and returned to the pool.
+    ///
+    /// Defaults to None.
+    pub fn connection_customizer(
+        mut self,
+        connection_customizer: Box<dyn CustomizeConnection<M::Connection, M::Error>>,
+    ) -> Builder<M> {
+        self.connection_customizer = Some(connection_customizer);
+        self
+    }
+
     /// Used by tests
     #[allow(dead_code)]
     pub fn reaper_rate(mut self, reaper_rate: Duration) -> Builder<M> {
@@ -336,8 +966,10 @@ impl<M: ManageConnection> Builder<M> {
     /// Consumes the builder, returning a new, initialized `Pool`.
     ///
     /// The `Pool` will not be returned until it has established its configured
-    /// minimum number of connections, or it times out.
-    pub async fn build(self, manager: M) -> Result<Pool<M>, M::Error> {
+    /// minimum number of connections, or it times out. If the last attempt to
+    /// establish a connection failed with an error, that error is returned;
+    /// if it timed out, `RunError::TimedOut` is returned.
+    pub async fn build(self, manager: M) -> Result<Pool<M>, RunError<M::Error>> {
         let pool = self.build_inner(manager);
         pool.replenish_idle_connections().await.map(|()| pool)
     }
@@ -355,118 +987,488 @@ impl<M: ManageConnection> Builder<M> {
 
 /// The pool data that must be protected by a lock.
 #[allow(missing_debug_implementations)]
-struct PoolInternals<C>
+struct PoolInternals<M>
 where
-    C: Send,
+    M: ManageConnection,
 {
-    waiters: VecDeque<oneshot::Sender<Conn<C>>>,
-    conns: VecDeque<IdleConn<C>>,
+    waiters: VecDeque<Waiter<M>>,
+    conns: VecDeque<IdleConn<M::Connection>>,
     num_conns: u32,
     pending_conns: u32,
+    closing_conns: u32,
+    closed: bool,
+    closing: Option<oneshot::Sender<()>>,
+    close_waiters: Vec<oneshot::Sender<()>>,
+    limits: Limits,
+    last_connect_error: Option<String>,
+    priority_aging: Duration,
+    queue_strategy: QueueStrategy,
+    leases: HashMap<u64, Lease>,
+    next_lease: u64,
 }
 
-impl<C> PoolInternals<C>
-where
-    C: Send,
-{
-    fn put_idle_conn(&mut self, mut conn: IdleConn<C>) {
-        loop {
-            if let Some(waiter) = self.waiters.pop_front() {
-                // This connection is no longer idle, send it back out.
-                match waiter.send(conn.conn) {
-                    Ok(_) => break,
-                    // Oops, that receiver was gone. Loop and try again.
-                    Err(c) => conn.conn = c,
-                }
-            } else {
-                // Queue it in the idle queue.
-                self.conns.push_back(conn);
-                break;
-            }
-        }
-    }
+/// Where and when a `PooledConnection` was checked out, for leak detection.
+struct Lease {
+    location: &'static Location<'static>,
+    since: Instant,
+    reported: bool,
 }
 
-/// The guts of a `Pool`.
-#[allow(missing_debug_implementations)]
-struct SharedPool<M>
-where
-    M: ManageConnection + Send,
-{
-    statics: Builder<M>,
-    manager: M,
-    internals: Mutex<PoolInternals<M::Connection>>,
+/// The pool settings that can be changed after the pool has been built.
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_size: u32,
+    min_idle: Option<u32>,
+    max_lifetime: Option<Duration>,
+    idle_timeout: Option<Duration>,
 }
 
-impl<M> SharedPool<M>
+/// A caller waiting for a connection, or for the error that prevented one from
+/// being established.
+struct Waiter<M>
 where
     M: ManageConnection,
 {
-    async fn sink_error<'a, E, F, T>(&self, f: F) -> Result<T, ()>
-    where
-        F: Future<Output = Result<T, E>> + Send + 'a,
-        E: Into<M::Error>,
-    {
-        let sink = self.statics.error_sink.boxed_clone();
-        f.await.map_err(|e| sink.sink(e.into()))
-    }
-
-    async fn or_timeout<'a, E, F, T>(&self, f: F) -> Result<Option<T>, E>
-    where
-        F: Future<Output = Result<T, E>> + Send + 'a,
-        T: Send + 'a,
-        E: Send + ::std::fmt::Debug + 'a,
-    {
-        timeout(self.statics.connection_timeout, f)
-            .map(|r| match r {
-                Ok(Ok(item)) => Ok(Some(item)),
-                Ok(Err(e)) => Err(e),
-                Err(_) => Ok(None),
-            })
-            .await
-    }
+    tx: oneshot::Sender<Result<Conn<M::Connection>, M::Error>>,
+    priority: Priority,
+    since: Instant,
 }
 
-/// A generic connection pool.
-pub struct Pool<M>
+impl<M> Waiter<M>
 where
     M: ManageConnection,
 {
-    inner: Arc<SharedPool<M>>,
+    /// How urgently this waiter should be served, expressed as a wait time.
+    ///
+    /// Every priority level counts as having waited for another `aging`, so a
+    /// waiter eventually overtakes newer waiters of a higher priority.
+    fn urgency(&self, now: Instant, aging: Duration) -> Duration {
+        aging * self.priority as u32 + (now - self.since)
+    }
 }
 
-impl<M> Clone for Pool<M>
+impl<M> PoolInternals<M>
 where
     M: ManageConnection,
 {
-    fn clone(&self) -> Self {
-        Pool {
-            inner: self.inner.clone(),
+    fn state(&self) -> State {
+        State {
+            connections: self.num_conns,
+            idle_connections: self.conns.len() as u32,
+            pending_connections: self.pending_conns,
+            waiters: self.waiters.len() as u32,
         }
     }
-}
 
-impl<M> fmt::Debug for Pool<M>
-where
-    M: ManageConnection,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_fmt(format_args!("Pool({:p})", self.inner))
+    fn timed_out<E>(&self, waited: Duration) -> RunError<E> {
+        RunError::TimedOut(TimeoutDiagnostics {
+            waited,
+            state: self.state(),
+            last_connect_error: self.last_connect_error.clone(),
+        })
     }
-}
 
-// Outside of Pool to avoid borrow splitting issues on self
-async fn add_connection<M>(pool: Arc<SharedPool<M>>) -> Result<(), M::Error>
-where
-    M: ManageConnection,
+    /// Wakes up `Pool::close` callers once a closed pool has no connections
+    /// left, and every connection it discarded has finished closing.
+    fn notify_if_drained(&mut self) {
+        if self.closed && self.num_conns + self.pending_conns + self.closing_conns == 0 {
+            for tx in self.close_waiters.drain(..) {
+                let _ = tx.send(());
+            }
+        }
+    }
+
+    /// Removes the most urgent waiter from the queue.
+    fn pop_waiter(&mut self) -> Option<Waiter<M>> {
+        let now = Instant::now();
+        let aging = self.priority_aging;
+        let mut best: Option<(usize, Duration)> = None;
+        for (i, waiter) in self.waiters.iter().enumerate() {
+            let urgency = waiter.urgency(now, aging);
+            // Strictly greater, so that ties are served first come, first served.
+            if best.is_none_or(|(_, best)| urgency > best) {
+                best = Some((i, urgency));
+            }
+        }
+        best.and_then(|(i, _)| self.waiters.remove(i))
+    }
+
+    fn put_idle_conn(&mut self, mut conn: IdleConn<M::Connection>) {
+        loop {
+            if let Some(waiter) = self.pop_waiter() {
+                // This connection is no longer idle, send it back out.
+                match waiter.tx.send(Ok(conn.conn)) {
+                    Ok(_) => break,
+                    // Oops, that receiver was gone. Loop and try again.
+                    Err(c) => conn.conn = c.ok().unwrap(),
+                }
+            } else {
+                // Queue it in the idle queue, where connections are always
+                // taken from the front.
+                match self.queue_strategy {
+                    QueueStrategy::Fifo => self.conns.push_back(conn),
+                    QueueStrategy::Lifo => self.conns.push_front(conn),
+                }
+                break;
+            }
+        }
+    }
+
+    /// Returns when the connection that has been idle the longest went idle.
+    fn stalest_idle_start(&self) -> Option<Instant> {
+        match self.queue_strategy {
+            QueueStrategy::Fifo => self.conns.front(),
+            QueueStrategy::Lifo => self.conns.back(),
+        }
+        .map(|conn| conn.idle_start)
+    }
+
+    /// Puts a connection that was taken out of the idle queue without being
+    /// used back in its place, keeping the queue ordered by `idle_start`.
+    fn restore_idle_conn(&mut self, conn: IdleConn<M::Connection>) {
+        if !self.waiters.is_empty() {
+            return self.put_idle_conn(conn);
+        }
+
+        let strategy = self.queue_strategy;
+        let pos = self
+            .conns
+            .iter()
+            .position(|other| match strategy {
+                QueueStrategy::Fifo => other.idle_start > conn.idle_start,
+                QueueStrategy::Lifo => other.idle_start < conn.idle_start,
+            })
+            .unwrap_or(self.conns.len());
+        self.conns.insert(pos, conn);
+    }
+
+    /// Removes up to `max` idle connections which haven't been checked for at
+    /// least `period`.
+    fn take_unchecked(&mut self, period: Duration, max: usize) -> Vec<IdleConn<M::Connection>> {
+        let now = Instant::now();
+        let mut unchecked = Vec::new();
+        let mut i = 0;
+        while i < self.conns.len() && unchecked.len() < max {
+            if now - self.conns[i].last_checked >= period {
+                unchecked.extend(self.conns.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        unchecked
+    }
+
+    /// Removes the connection that has been idle the longest.
+    fn pop_stalest_idle(&mut self) -> Option<IdleConn<M::Connection>> {
+        match self.queue_strategy {
+            QueueStrategy::Fifo => self.conns.pop_front(),
+            QueueStrategy::Lifo => self.conns.pop_back(),
+        }
+    }
+
+    /// Hands an error to the most urgent waiter, or gives it back if there is none.
+    fn put_error(&mut self, mut error: M::Error) -> Result<(), M::Error> {
+        while let Some(waiter) = self.pop_waiter() {
+            match waiter.tx.send(Err(error)) {
+                Ok(_) => return Ok(()),
+                Err(e) => error = e.err().unwrap(),
+            }
+        }
+        Err(error)
+    }
+}
+
+/// The outcome of trying to take an idle connection.
+enum Checkout<M>
+where
+    M: ManageConnection,
+{
+    /// An idle connection was available.
+    Idle(Conn<M::Connection>),
+    /// There were no idle connections, so a waiter has been queued.
+    Wait(oneshot::Receiver<Result<Conn<M::Connection>, M::Error>>),
+    /// There were no idle connections, and the caller didn't want to wait.
+    Empty,
+}
+
+/// A caller's place in the waiter queue.
+///
+/// If the caller stops waiting, because it timed out or was cancelled, a
+/// connection which was sent to it in the meantime goes back to the pool.
+struct WaitGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    pool: &'a Pool<M>,
+    rx: oneshot::Receiver<Result<Conn<M::Connection>, M::Error>>,
+}
+
+impl<'a, M> Drop for WaitGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    fn drop(&mut self) {
+        self.rx.close();
+        if let Ok(Some(Ok(conn))) = self.rx.try_recv() {
+            self.pool.return_conn(conn);
+        }
+    }
+}
+
+/// Frees the slot of a checked out connection unless disarmed, i.e. if the
+/// future holding the connection panicked or was cancelled before handing it
+/// back to the pool.
+struct SlotGuard<'a, M>
+where
+    M: ManageConnection,
 {
-    let mut internals = pool.internals.lock().await;
-    if internals.num_conns + internals.pending_conns >= pool.statics.max_size {
-        return Ok(());
+    pool: &'a Pool<M>,
+    armed: bool,
+}
+
+impl<'a, M> SlotGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    fn new(pool: &'a Pool<M>) -> Self {
+        SlotGuard { pool, armed: true }
     }
 
-    internals.pending_conns += 1;
-    mem::drop(internals);
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl<'a, M> Drop for SlotGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    fn drop(&mut self) {
+        if self.armed {
+            self.pool.forget_conn();
+        }
+    }
+}
+
+/// The guts of a `Pool`.
+#[allow(missing_debug_implementations)]
+struct SharedPool<M>
+where
+    M: ManageConnection + Send,
+{
+    statics: Builder<M>,
+    manager: Arc<M>,
+    internals: Mutex<PoolInternals<M>>,
+    state: AtomicState,
+    statistics: AtomicStatistics,
+    /// The runtime the pool was built on, so that background work can be
+    /// spawned from threads outside of it, e.g. when dropping a connection.
+    handle: Handle,
+    /// Resolves once the pool has been closed.
+    closed: Shared<oneshot::Receiver<()>>,
+}
+
+impl<M> Drop for SharedPool<M>
+where
+    M: ManageConnection,
+{
+    fn drop(&mut self) {
+        let internals = match self.internals.get_mut() {
+            Ok(internals) => internals,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        // Nobody can be waiting on `Pool::close` anymore, so there is no need
+        // to keep track of these.
+        for conn in mem::take(&mut internals.conns) {
+            let manager = self.manager.clone();
+            self.run_close(async move { manager.close(conn.conn.conn).await }.boxed());
+        }
+    }
+}
+
+/// A lock on the pool internals which publishes the pool gauges when released.
+struct InternalsGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    internals: MutexGuard<'a, PoolInternals<M>>,
+    state: &'a AtomicState,
+}
+
+impl<'a, M> Deref for InternalsGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    type Target = PoolInternals<M>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.internals
+    }
+}
+
+impl<'a, M> DerefMut for InternalsGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.internals
+    }
+}
+
+impl<'a, M> Drop for InternalsGuard<'a, M>
+where
+    M: ManageConnection,
+{
+    fn drop(&mut self) {
+        self.state.store(self.internals.state());
+    }
+}
+
+impl<M> SharedPool<M>
+where
+    M: ManageConnection,
+{
+    /// Establishes a new connection and runs the `on_connect` hook on it.
+    async fn connect(self: &Arc<Self>) -> Result<M::Connection, M::Error> {
+        let mut conn = self.manager.connect().await?;
+        if let Some(customizer) = &self.statics.connection_customizer {
+            if let Err(e) = customizer.on_connect(&mut conn).await {
+                self.lock().closing_conns += 1;
+                self.close_conn(conn);
+                return Err(e);
+            }
+        }
+        Ok(conn)
+    }
+
+    fn lock(&self) -> InternalsGuard<'_, M> {
+        InternalsGuard {
+            internals: self.internals.lock().unwrap(),
+            state: &self.state,
+        }
+    }
+
+    /// Runs `f` to completion, unless the pool is closed first.
+    async fn unless_closed<F>(&self, f: F) -> Option<F::Output>
+    where
+        F: Future + Send,
+    {
+        match future::select(f.boxed(), self.closed.clone()).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+
+    /// Releases the pending slot of a connection attempt which was abandoned
+    /// because the pool has been closed, so that it doesn't hold up `Pool::close`.
+    fn give_up(&self) -> RunError<M::Error> {
+        let mut locked = self.lock();
+        locked.pending_conns -= 1;
+        locked.notify_if_drained();
+        RunError::PoolClosed
+    }
+
+    /// Closes a connection the pool is done with through `ManageConnection::close`.
+    ///
+    /// The connection must have been counted in `closing_conns`, e.g. through
+    /// `discard_connections`, so that `Pool::close` waits for it. This runs
+    /// user code, so it must not be called with the pool lock held.
+    fn close_conn(self: &Arc<Self>, conn: M::Connection) {
+        let pool = self.clone();
+        self.run_close(
+            async move {
+                pool.manager.close(conn).await;
+                let mut locked = pool.lock();
+                locked.closing_conns -= 1;
+                locked.notify_if_drained();
+            }
+            .boxed(),
+        );
+    }
+
+    /// Runs a call to `ManageConnection::close` to completion.
+    fn run_close(&self, mut close: BoxFuture<'static, ()>) {
+        // Closing usually completes right away, e.g. with the default of just
+        // dropping the connection, so only spawn a task if it doesn't. Poll it
+        // within the runtime either way, as this may run on any thread.
+        let handle = &self.handle;
+        if handle.enter(|| (&mut close).now_or_never()).is_none() {
+            handle.spawn(close);
+        }
+    }
+
+    async fn sink_error<'a, F, T>(&self, f: F) -> Result<T, ()>
+    where
+        F: Future<Output = Result<T, RunError<M::Error>>> + Send + 'a,
+    {
+        let sink = self.statics.error_sink.boxed_clone();
+        f.await.map_err(|e| {
+            // Connection attempts that timed out have no error to report.
+            if let RunError::User(e) = e {
+                sink.sink(e)
+            }
+        })
+    }
+
+    async fn or_timeout<'a, E, F, T>(&self, deadline: Instant, f: F) -> Result<Option<T>, E>
+    where
+        F: Future<Output = Result<T, E>> + Send + 'a,
+        T: Send + 'a,
+        E: Send + ::std::fmt::Debug + 'a,
+    {
+        timeout_at(deadline.into(), f)
+            .map(|r| match r {
+                Ok(Ok(item)) => Ok(Some(item)),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Ok(None),
+            })
+            .await
+    }
+}
+
+/// A generic connection pool.
+pub struct Pool<M>
+where
+    M: ManageConnection,
+{
+    inner: Arc<SharedPool<M>>,
+}
+
+impl<M> Clone for Pool<M>
+where
+    M: ManageConnection,
+{
+    fn clone(&self) -> Self {
+        Pool {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M> fmt::Debug for Pool<M>
+where
+    M: ManageConnection,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_fmt(format_args!("Pool({:p})", self.inner))
+    }
+}
+
+// Outside of Pool to avoid borrow splitting issues on self
+async fn add_connection<M>(pool: Arc<SharedPool<M>>) -> Result<(), RunError<M::Error>>
+where
+    M: ManageConnection,
+{
+    {
+        let mut internals = pool.lock();
+        if internals.closed
+            || internals.num_conns + internals.pending_conns >= internals.limits.max_size
+        {
+            return Ok(());
+        }
+
+        internals.pending_conns += 1;
+    }
 
     let new_shared = Arc::downgrade(&pool);
     let shared = match new_shared.upgrade() {
@@ -475,50 +1477,134 @@ where
     };
 
     let start = Instant::now();
-    let mut delay = Duration::from_secs(0);
+    let budget = pool.statics.connect_retry_budget;
+    let mut attempts = 0;
+    let mut last_error = None;
     loop {
-        match shared.manager.connect().await {
-            Ok(conn) => {
+        // No single attempt may outlast the retry budget, so a hung connect
+        // can't hold on to its pending slot forever.
+        let remaining = budget.checked_sub(start.elapsed()).unwrap_or_default();
+        let attempt_timeout = match pool.statics.connect_timeout {
+            Some(connect_timeout) => min(connect_timeout, remaining),
+            None => remaining,
+        };
+
+        // Closing the pool abandons the attempt, rather than waiting it out.
+        let attempt = timeout(attempt_timeout, shared.connect());
+        let res = match shared.unless_closed(attempt).await {
+            Some(res) => res,
+            None => return Err(shared.give_up()),
+        };
+
+        match res {
+            Ok(Ok(conn)) => {
                 let now = Instant::now();
                 let conn = IdleConn {
-                    conn: Conn { conn, birth: now },
+                    conn: Conn {
+                        conn,
+                        birth: now,
+                        uses: 0,
+                    },
                     idle_start: now,
+                    last_checked: now,
                 };
 
-                let mut locked = shared.internals.lock().await;
+                AtomicStatistics::record(&shared.statistics.connections_created, 1);
+                let mut locked = shared.lock();
                 locked.pending_conns -= 1;
-                locked.num_conns += 1;
-                locked.put_idle_conn(conn);
+                locked.last_connect_error = None;
+                if locked.closed {
+                    locked.closing_conns += 1;
+                    drop(locked);
+                    shared.close_conn(conn.conn.conn);
+                } else {
+                    locked.num_conns += 1;
+                    locked.put_idle_conn(conn);
+                }
                 return Ok(());
             }
-            Err(e) => {
-                if Instant::now() - start > pool.statics.connection_timeout {
-                    let mut locked = shared.internals.lock().await;
-                    locked.pending_conns -= 1;
-                    return Err(e);
-                } else {
-                    delay = max(Duration::from_millis(200), delay);
-                    delay = min(pool.statics.connection_timeout / 2, delay * 2);
-                    delay_for(delay).await;
+            res => {
+                AtomicStatistics::record(&shared.statistics.connections_failed, 1);
+                // Prefer reporting an actual error over a timed out attempt.
+                let mut retryable = true;
+                if let Ok(Err(e)) = res {
+                    retryable = shared.manager.is_retryable(&e);
+                    shared.lock().last_connect_error = Some(e.to_string());
+                    last_error = Some(e);
+                }
+
+                // Give up right away if there's no budget left for another
+                // attempt after backing off.
+                attempts += 1;
+                let remaining = budget.checked_sub(start.elapsed()).unwrap_or_default();
+                let delay = match pool.statics.backoff.next_delay(attempts) {
+                    Some(delay) if retryable && delay < remaining => delay,
+                    _ if !retryable => {
+                        // Don't leave a waiter hanging on an error that won't go away.
+                        let mut locked = shared.lock();
+                        locked.pending_conns -= 1;
+                        locked.notify_if_drained();
+                        let res = locked.put_error(last_error.unwrap());
+
+                        // Give every other waiter an attempt of its own, so that
+                        // they fail just as fast if the error persists.
+                        locked.waiters.retain(|w| !w.tx.is_canceled());
+                        if !locked.closed
+                            && !locked.waiters.is_empty()
+                            && locked.num_conns + locked.pending_conns < locked.limits.max_size
+                        {
+                            spawn_add_connection(&shared);
+                        }
+
+                        return res.map_err(RunError::User);
+                    }
+                    _ => {
+                        let mut locked = shared.lock();
+                        locked.pending_conns -= 1;
+                        locked.notify_if_drained();
+                        return Err(match last_error {
+                            Some(e) => RunError::User(e),
+                            None => locked.timed_out(start.elapsed()),
+                        });
+                    }
+                };
+
+                if shared.unless_closed(delay_for(delay)).await.is_none() {
+                    return Err(shared.give_up());
                 }
             }
         }
     }
 }
 
+// Spawn an attempt to establish a new connection. This is a plain function, so
+// that `add_connection` can also use it to spawn further attempts.
+fn spawn_add_connection<M>(pool: &Arc<SharedPool<M>>)
+where
+    M: ManageConnection,
+{
+    let inner = pool.clone();
+    pool.handle.spawn(async move {
+        let f = add_connection(inner.clone());
+        inner.sink_error(f).map(|_| ()).await;
+    });
+}
+
 // Drop connections
 // NB: This is called with the pool lock held.
-fn drop_connections<'a, M>(
-    pool: &Arc<SharedPool<M>>,
-    internals: &mut MutexGuard<'a, PoolInternals<M::Connection>>,
-    dropped: usize,
-) where
+fn drop_connections<M>(pool: &Arc<SharedPool<M>>, internals: &mut PoolInternals<M>, dropped: usize)
+where
     M: ManageConnection,
 {
     internals.num_conns -= dropped as u32;
+    if internals.closed {
+        internals.notify_if_drained();
+        return;
+    }
+
     // We might need to spin up more connections to maintain the idle limit, e.g.
     // if we hit connection lifetime limits
-    if internals.num_conns + internals.pending_conns < pool.statics.max_size {
+    if internals.num_conns + internals.pending_conns < internals.limits.max_size {
         Pool {
             inner: pool.clone(),
         }
@@ -526,6 +1612,19 @@ fn drop_connections<'a, M>(
     }
 }
 
+// Drop connections which are about to be passed to `SharedPool::close_conn`.
+// NB: This is called with the pool lock held.
+fn discard_connections<M>(
+    pool: &Arc<SharedPool<M>>,
+    internals: &mut PoolInternals<M>,
+    discarded: usize,
+) where
+    M: ManageConnection,
+{
+    internals.closing_conns += discarded as u32;
+    drop_connections(pool, internals, discarded);
+}
+
 fn schedule_reaping<M>(mut interval: Interval, weak_shared: Weak<SharedPool<M>>)
 where
     M: ManageConnection,
@@ -534,23 +1633,51 @@ where
         loop {
             let _ = interval.tick().await;
             if let Some(pool) = weak_shared.upgrade() {
-                let mut internals = pool.internals.lock().await;
+                let mut internals = pool.lock();
+                if internals.closed {
+                    break;
+                }
                 let now = Instant::now();
-                let before = internals.conns.len();
+                let limits = internals.limits;
+                let mut expired = Vec::new();
 
-                internals.conns.retain(|conn| {
-                    let mut keep = true;
-                    if let Some(timeout) = pool.statics.idle_timeout {
-                        keep &= now - conn.idle_start < timeout;
+                for conn in mem::take(&mut internals.conns) {
+                    match limits.max_lifetime {
+                        Some(lifetime) if now - conn.conn.birth >= lifetime => expired.push(conn),
+                        _ => internals.conns.push_back(conn),
                     }
-                    if let Some(lifetime) = pool.statics.max_lifetime {
-                        keep &= now - conn.conn.birth < lifetime;
+                }
+                let max_lifetimes = expired.len();
+
+                if let Some(timeout) = limits.idle_timeout {
+                    // Close the connections that have been idle the longest
+                    // first, keeping `min_idle` of them around.
+                    let min_idle = limits.min_idle.unwrap_or(0) as usize;
+                    while internals.conns.len() > min_idle {
+                        match internals.stalest_idle_start() {
+                            Some(idle_start) if now - idle_start >= timeout => {
+                                expired.extend(internals.pop_stalest_idle());
+                            }
+                            _ => break,
+                        }
                     }
-                    keep
-                });
+                }
 
-                let dropped = before - internals.conns.len();
-                drop_connections(&pool, &mut internals, dropped);
+                let idle_timeouts = expired.len() - max_lifetimes;
+                let stats = &pool.statistics;
+                AtomicStatistics::record(
+                    &stats.connections_closed_idle_timeout,
+                    idle_timeouts as u64,
+                );
+                AtomicStatistics::record(
+                    &stats.connections_closed_max_lifetime,
+                    max_lifetimes as u64,
+                );
+                discard_connections(&pool, &mut internals, expired.len());
+                drop(internals);
+                for conn in expired {
+                    pool.close_conn(conn.conn.conn);
+                }
             } else {
                 break;
             }
@@ -558,61 +1685,219 @@ where
     });
 }
 
+fn schedule_health_checks<M>(period: Duration, weak_shared: Weak<SharedPool<M>>)
+where
+    M: ManageConnection,
+{
+    let mut interval = interval_at((Instant::now() + period).into(), period);
+    spawn(async move {
+        loop {
+            let _ = interval.tick().await;
+            let pool = match weak_shared.upgrade() {
+                Some(pool) => pool,
+                None => break,
+            };
+
+            // Only check connections which have been sitting idle for a whole
+            // period; the others have been in use since the last check. Take a
+            // few of them at a time, so that the rest stay available.
+            loop {
+                let unchecked = {
+                    let mut internals = pool.lock();
+                    if internals.closed {
+                        return;
+                    }
+                    let batch = max(1, internals.conns.len() / 4);
+                    internals.take_unchecked(period, batch)
+                };
+                if unchecked.is_empty() {
+                    break;
+                }
+
+                // A check that hangs would keep its connection out of the idle
+                // queue for good.
+                let check_timeout = pool.statics.idle_health_check_timeout;
+                let checks = unchecked.into_iter().map(|mut conn| {
+                    let pool = &pool;
+                    async move {
+                        let res =
+                            timeout(check_timeout, pool.manager.is_valid(&mut conn.conn.conn))
+                                .await;
+                        (conn, res)
+                    }
+                });
+                let checked = future::join_all(checks).await;
+
+                let mut errors = Vec::new();
+                let mut invalid = Vec::new();
+                {
+                    let mut internals = pool.lock();
+                    for (mut conn, res) in checked {
+                        match res {
+                            Ok(Ok(())) if !internals.closed => {
+                                conn.last_checked = Instant::now();
+                                internals.restore_idle_conn(conn);
+                            }
+                            Ok(Ok(())) => invalid.push(conn),
+                            res => {
+                                AtomicStatistics::record(
+                                    &pool.statistics.connections_closed_invalid,
+                                    1,
+                                );
+                                if let Ok(Err(e)) = res {
+                                    errors.push(e);
+                                }
+                                invalid.push(conn);
+                            }
+                        }
+                    }
+                    discard_connections(&pool, &mut internals, invalid.len());
+                }
+
+                for conn in invalid {
+                    pool.close_conn(conn.conn.conn);
+                }
+                for e in errors {
+                    pool.statics.error_sink.sink(e);
+                }
+            }
+        }
+    });
+}
+
+fn schedule_leak_detection<M>(threshold: Duration, weak_shared: Weak<SharedPool<M>>)
+where
+    M: ManageConnection,
+{
+    // Scan several times per threshold, so that a lease taken just after a
+    // scan is still reported soon after it crosses the threshold.
+    let period = max(threshold / 4, Duration::from_millis(1));
+    let mut interval = interval_at((Instant::now() + threshold).into(), period);
+    spawn(async move {
+        loop {
+            let _ = interval.tick().await;
+            let pool = match weak_shared.upgrade() {
+                Some(pool) => pool,
+                None => break,
+            };
+
+            let now = Instant::now();
+            let mut internals = pool.lock();
+            if internals.closed {
+                break;
+            }
+            let leaks: Vec<_> = internals
+                .leases
+                .values_mut()
+                .filter(|lease| !lease.reported && now - lease.since >= threshold)
+                .map(|lease| {
+                    lease.reported = true;
+                    ConnectionLeak {
+                        location: lease.location,
+                        held_for: now - lease.since,
+                    }
+                })
+                .collect();
+            drop(internals);
+
+            for leak in leaks {
+                pool.statics.error_sink.sink_leak(leak);
+            }
+        }
+    });
+}
+
 impl<M: ManageConnection> Pool<M> {
     fn new_inner(builder: Builder<M>, manager: M) -> Pool<M> {
+        let (closing, closed) = oneshot::channel();
         let internals = PoolInternals {
             waiters: VecDeque::new(),
             conns: VecDeque::new(),
             num_conns: 0,
             pending_conns: 0,
+            closing_conns: 0,
+            closed: false,
+            closing: Some(closing),
+            close_waiters: Vec::new(),
+            last_connect_error: None,
+            priority_aging: builder.priority_aging,
+            queue_strategy: builder.queue_strategy,
+            leases: HashMap::new(),
+            next_lease: 0,
+            limits: Limits {
+                max_size: builder.max_size,
+                min_idle: builder.min_idle,
+                max_lifetime: builder.max_lifetime,
+                idle_timeout: builder.idle_timeout,
+            },
         };
 
         let shared = Arc::new(SharedPool {
             statics: builder,
-            manager,
+            manager: Arc::new(manager),
             internals: Mutex::new(internals),
+            state: AtomicState::default(),
+            statistics: AtomicStatistics::default(),
+            handle: Handle::current(),
+            closed: closed.shared(),
         });
 
-        if shared.statics.max_lifetime.is_some() || shared.statics.idle_timeout.is_some() {
-            let s = Arc::downgrade(&shared);
-            if let Some(shared) = s.upgrade() {
-                let start = Instant::now() + shared.statics.reaper_rate;
-                let interval = interval_at(start.into(), shared.statics.reaper_rate);
-                schedule_reaping(interval, s);
-            }
+        // The reaper always runs, since `max_lifetime` and `idle_timeout` may
+        // be enabled after the pool has been built.
+        let start = Instant::now() + shared.statics.reaper_rate;
+        let interval = interval_at(start.into(), shared.statics.reaper_rate);
+        schedule_reaping(interval, Arc::downgrade(&shared));
+
+        if let Some(period) = shared.statics.idle_health_check_interval {
+            schedule_health_checks(period, Arc::downgrade(&shared));
+        }
+
+        if let Some(threshold) = shared.statics.leak_detection_threshold {
+            schedule_leak_detection(threshold, Arc::downgrade(&shared));
         }
 
         Pool { inner: shared }
     }
 
-    async fn sink_error<'a, E, F, T>(&self, f: F) -> Result<T, ()>
+    async fn sink_error<'a, F, T>(&self, f: F) -> Result<T, ()>
     where
-        F: Future<Output = Result<T, E>> + Send + 'a,
-        E: Into<M::Error> + 'a,
+        F: Future<Output = Result<T, RunError<M::Error>>> + Send + 'a,
         T: 'a,
     {
         self.inner.sink_error(f).await
     }
 
-    async fn replenish_idle_connections(&self) -> Result<(), M::Error> {
-        let internals = self.inner.internals.lock().await;
+    async fn replenish_idle_connections(&self) -> Result<(), RunError<M::Error>> {
         let pool = self.inner.clone();
-        let slots_available = pool.statics.max_size - internals.num_conns - internals.pending_conns;
-        let idle = internals.conns.len() as u32;
-        let desired = pool.statics.min_idle.unwrap_or(0);
-
-        mem::drop(internals);
+        let (slots_available, idle, desired) = {
+            let internals = pool.lock();
+            (
+                internals
+                    .limits
+                    .max_size
+                    .saturating_sub(internals.num_conns + internals.pending_conns),
+                internals.conns.len() as u32,
+                internals.limits.min_idle.unwrap_or(0),
+            )
+        };
 
-        let stream = FuturesUnordered::new();
+        let mut stream = FuturesUnordered::new();
         for _ in idle..max(idle, min(desired, idle + slots_available)) {
             stream.push(add_connection(pool.clone()));
         }
 
-        stream.try_fold((), |_, _| ok(())).await
+        // Let every attempt run to completion, so that none of them is dropped
+        // while it holds a pending slot, and report the first error.
+        let mut res = Ok(());
+        while let Some(r) = stream.next().await {
+            res = res.and(r);
+        }
+        res
     }
 
     fn spawn_replenishing(self) {
-        spawn(async move {
+        let handle = self.inner.handle.clone();
+        handle.spawn(async move {
             let f = self.replenish_idle_connections();
             self.sink_error(f).map(|_| ()).await
         });
@@ -624,18 +1909,143 @@ impl<M: ManageConnection> Pool<M> {
     }
 
     /// Returns information about the current state of the pool.
+    ///
+    /// This never waits on the pool lock, so it is cheap enough to call from
+    /// a metrics scraper. The individual fields are not guaranteed to be
+    /// consistent with each other.
     pub fn state(&self) -> State {
-        let locked = loop {
-            if let Ok(internals) = self.inner.internals.try_lock() {
-                break internals;
+        self.inner.state.load()
+    }
+
+    /// Sets the maximum number of connections managed by the pool.
+    ///
+    /// When growing the pool, new connections are opened for callers waiting
+    /// on a connection. When shrinking it, idle connections in excess of the
+    /// new maximum are closed immediately, and checked out connections in
+    /// excess of it are closed when they are returned to the pool.
+    pub fn set_max_size(&self, max_size: u32) {
+        assert!(max_size > 0, "max_size must be greater than zero!");
+
+        let mut internals = self.inner.lock();
+        if let Some(min_idle) = internals.limits.min_idle {
+            if max_size < min_idle {
+                // Release the lock first, so that panicking doesn't poison it.
+                drop(internals);
+                panic!("min_idle must be no larger than max_size");
+            }
+        }
+        internals.limits.max_size = max_size;
+
+        let excess = internals.num_conns.saturating_sub(max_size);
+        let excess = min(excess as usize, internals.conns.len());
+        let closed: Vec<_> = (0..excess)
+            .filter_map(|_| internals.pop_stalest_idle())
+            .collect();
+        internals.num_conns -= closed.len() as u32;
+        internals.closing_conns += closed.len() as u32;
+
+        let slots_available =
+            max_size.saturating_sub(internals.num_conns + internals.pending_conns);
+        let wanted = (internals.waiters.len() as u32).saturating_sub(internals.pending_conns);
+        for _ in 0..min(wanted, slots_available) {
+            spawn_add_connection(&self.inner);
+        }
+
+        drop(internals);
+        for conn in closed {
+            self.inner.close_conn(conn.conn.conn);
+        }
+        self.clone().spawn_replenishing();
+    }
+
+    /// Sets the minimum idle connection count maintained by the pool.
+    ///
+    /// See `Builder::min_idle`.
+    pub fn set_min_idle(&self, min_idle: Option<u32>) {
+        {
+            let mut internals = self.inner.lock();
+            if let Some(min_idle) = min_idle {
+                if internals.limits.max_size < min_idle {
+                    // Release the lock first, so that panicking doesn't poison it.
+                    drop(internals);
+                    panic!("min_idle must be no larger than max_size");
+                }
             }
+            internals.limits.min_idle = min_idle;
+        }
+
+        self.clone().spawn_replenishing();
+    }
+
+    /// Sets the maximum lifetime of connections in the pool.
+    ///
+    /// See `Builder::max_lifetime`.
+    pub fn set_max_lifetime(&self, max_lifetime: Option<Duration>) {
+        assert!(
+            max_lifetime != Some(Duration::from_secs(0)),
+            "max_lifetime must be greater than zero!"
+        );
+        self.inner.lock().limits.max_lifetime = max_lifetime;
+    }
+
+    /// Sets the idle timeout used by the pool.
+    ///
+    /// See `Builder::idle_timeout`.
+    pub fn set_idle_timeout(&self, idle_timeout: Option<Duration>) {
+        assert!(
+            idle_timeout != Some(Duration::from_secs(0)),
+            "idle_timeout must be greater than zero!"
+        );
+        self.inner.lock().limits.idle_timeout = idle_timeout;
+    }
+
+    /// Closes the pool.
+    ///
+    /// Subsequent attempts to get a connection, as well as callers currently
+    /// waiting for one, fail with `RunError::PoolClosed`. Idle connections are
+    /// closed immediately, and checked out connections are closed as they are
+    /// returned to the pool.
+    ///
+    /// The returned future resolves once every connection has been closed
+    /// through `ManageConnection::close`. If a `timeout` is given and
+    /// connections are still checked out or closing when it expires, the
+    /// future resolves with `RunError::TimedOut` instead.
+    pub async fn close(&self, timeout: Option<Duration>) -> Result<(), RunError<M::Error>> {
+        let (idle, rx) = {
+            let mut internals = self.inner.lock();
+            internals.closed = true;
+            if let Some(closing) = internals.closing.take() {
+                let _ = closing.send(());
+            }
+            internals.waiters.clear();
+
+            // This resolves right away if there is nothing left to wait for.
+            let (tx, rx) = oneshot::channel();
+            internals.close_waiters.push(tx);
+            let idle = mem::take(&mut internals.conns);
+            discard_connections(&self.inner, &mut internals, idle.len());
+            (idle, rx)
         };
 
-        State {
-            connections: locked.num_conns,
-            idle_connections: locked.conns.len() as u32,
-            _p: (),
+        for conn in idle {
+            self.inner.close_conn(conn.conn.conn);
         }
+
+        match timeout {
+            Some(timeout) => match self.inner.or_timeout(Instant::now() + timeout, rx).await {
+                Ok(Some(())) => Ok(()),
+                _ => Err(self.inner.lock().timed_out(timeout)),
+            },
+            None => {
+                let _ = rx.await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns cumulative statistics about the pool.
+    pub fn statistics(&self) -> Statistics {
+        self.inner.statistics.load()
     }
 
     /// Run a closure with a `Connection`.
@@ -646,101 +2056,468 @@ impl<M: ManageConnection> Pool<M> {
         E: From<M::Error> + Send + 'a,
         T: Send + 'a,
     {
-        let conn = match self.get_conn::<E>().await {
+        let deadline = Instant::now() + self.inner.statics.checkout_timeout;
+        let conn = match self.get_conn::<E>(Some(deadline), Priority::Normal).await {
             Ok(conn) => conn,
             Err(e) => return Err(e),
         };
 
-        let birth = conn.birth;
+        // The connection is out of our hands until `f` gives it back.
+        let slot = SlotGuard::new(self);
+        let (birth, uses) = (conn.birth, conn.uses);
         let (r, conn): (Result<_, E>, _) = match f(conn.conn).await {
             Ok((t, conn)) => (Ok(t), conn),
             Err((e, conn)) => (Err(e), conn),
         };
 
-        self.put_back(birth, conn).await;
+        self.release(Conn { conn, birth, uses }).await;
+        slot.disarm();
 
         r.map_err(RunError::User)
     }
 
+    /// Run a closure with a borrowed `Connection`.
+    ///
+    /// Unlike `run`, the closure doesn't have to hand the connection back, so
+    /// `?` can be used freely in its body. The future it returns has to be
+    /// boxed, e.g. with `FutureExt::boxed`, since it borrows the connection.
+    ///
+    /// If the future panics or is cancelled, the connection is dropped rather
+    /// than returned to the pool, since it might be in an unknown state.
+    pub async fn with_connection<T, E, F>(&self, f: F) -> Result<T, RunError<E>>
+    where
+        F: for<'c> FnOnce(&'c mut M::Connection) -> BoxFuture<'c, Result<T, E>> + Send,
+        E: From<M::Error> + Send,
+        T: Send,
+    {
+        let deadline = Instant::now() + self.inner.statics.checkout_timeout;
+        let mut conn = self.get_conn::<E>(Some(deadline), Priority::Normal).await?;
+
+        let slot = SlotGuard::new(self);
+        let r = f(&mut conn.conn).await;
+        self.release(conn).await;
+        slot.disarm();
+
+        r.map_err(RunError::User)
+    }
+
+    /// Run a closure with a borrowed `Connection`, retrying transient errors.
+    ///
+    /// This works like `with_connection`, except that if the closure fails
+    /// with an error which `policy` considers transient, the connection is
+    /// discarded and the closure is run again on a fresh connection after the
+    /// delay given by the policy's `Backoff`. Errors checking out a connection
+    /// are returned right away.
+    pub async fn run_with_retry<T, E, F, P>(&self, policy: &P, mut f: F) -> Result<T, RunError<E>>
+    where
+        F: for<'c> FnMut(&'c mut M::Connection) -> BoxFuture<'c, Result<T, E>> + Send,
+        P: RetryPolicy<E> + ?Sized,
+        E: From<M::Error> + Send,
+        T: Send,
+    {
+        let mut attempts = 0;
+        loop {
+            let deadline = Instant::now() + self.inner.statics.checkout_timeout;
+            let mut conn = self.get_conn::<E>(Some(deadline), Priority::Normal).await?;
+
+            let slot = SlotGuard::new(self);
+            let r = f(&mut conn.conn).await;
+            let e = match r {
+                Err(e) if policy.is_transient(&e) => e,
+                r => {
+                    self.release(conn).await;
+                    slot.disarm();
+                    return r.map_err(RunError::User);
+                }
+            };
+
+            // The connection itself may be what failed, so don't reuse it.
+            AtomicStatistics::record(&self.inner.statistics.connections_closed_retried, 1);
+            self.close_conn(conn.conn);
+            slot.disarm();
+
+            attempts += 1;
+            match policy.backoff().next_delay(attempts) {
+                Some(delay) => delay_for(delay).await,
+                None => return Err(RunError::User(e)),
+            }
+        }
+    }
+
     /// Return connection back in to the pool
-    async fn put_back(&self, birth: Instant, mut conn: M::Connection) {
+    ///
+    /// This never waits on anything, so it is safe to call from `Drop`. If
+    /// the connection needs to be released asynchronously, that happens on a
+    /// spawned task.
+    fn put_back(&self, conn: Conn<M::Connection>) {
+        let statics = &self.inner.statics;
+        if statics.connection_customizer.is_some() || statics.test_on_check_in {
+            let pool = self.clone();
+            self.inner.handle.spawn(async move {
+                let slot = SlotGuard::new(&pool);
+                pool.release(conn).await;
+                slot.disarm();
+            });
+        } else {
+            self.return_conn(conn);
+        }
+    }
+
+    /// Closes a connection which its user found to be broken.
+    fn discard_conn(&self, conn: Conn<M::Connection>) {
+        AtomicStatistics::record(&self.inner.statistics.connections_closed_broken, 1);
+        self.close_conn(conn.conn);
+    }
+
+    /// Closes a connection which won't be returned to the pool, and frees its slot.
+    fn close_conn(&self, conn: M::Connection) {
+        let mut locked = self.inner.lock();
+        discard_connections(&self.inner, &mut locked, 1);
+        drop(locked);
+        self.inner.close_conn(conn);
+    }
+
+    /// Frees the slot of a connection which was detached from the pool, or lost.
+    fn forget_conn(&self) {
+        let mut locked = self.inner.lock();
+        drop_connections(&self.inner, &mut locked, 1);
+    }
+
+    /// Runs the asynchronous release steps for a connection, then returns it.
+    async fn release(&self, mut conn: Conn<M::Connection>) {
+        let inner = self.inner.clone();
+        if let Some(customizer) = &inner.statics.connection_customizer {
+            if let Err(e) = customizer.on_release(&mut conn.conn).await {
+                inner.statics.error_sink.sink(e);
+                self.close_conn(conn.conn);
+                return;
+            }
+        }
+
+        if inner.statics.test_on_check_in {
+            if let Err(e) = inner.manager.recycle(&mut conn.conn).await {
+                AtomicStatistics::record(&inner.statistics.connections_closed_recycle, 1);
+                inner.statics.error_sink.sink(e);
+                self.close_conn(conn.conn);
+                return;
+            }
+        }
+
+        self.return_conn(conn);
+    }
+
+    fn return_conn(&self, mut conn: Conn<M::Connection>) {
         let inner = self.inner.clone();
 
         // Supposed to be fast, but do it before locking anyways.
-        let broken = inner.manager.has_broken(&mut conn);
+        let broken = inner.manager.has_broken(&mut conn.conn);
 
-        let mut locked = inner.internals.lock().await;
+        let worn_out = match inner.statics.max_uses {
+            Some(max_uses) => conn.uses >= max_uses,
+            None => false,
+        };
+
+        let mut locked = inner.lock();
         if broken {
-            drop_connections(&inner, &mut locked, 1);
-        } else {
-            let conn = IdleConn::make_idle(Conn { conn, birth });
-            locked.put_idle_conn(conn);
+            AtomicStatistics::record(&inner.statistics.connections_closed_broken, 1);
+        } else if worn_out {
+            AtomicStatistics::record(&inner.statistics.connections_closed_max_uses, 1);
+        } else if !locked.closed && locked.num_conns <= locked.limits.max_size {
+            locked.put_idle_conn(IdleConn::make_idle(conn));
+            return;
         }
+
+        // The connection is unusable, or the pool has been closed or shrunk
+        // while it was out. Close it once the lock is released.
+        discard_connections(&inner, &mut locked, 1);
+        drop(locked);
+        inner.close_conn(conn.conn);
     }
 
-    async fn get_conn<E>(&self) -> Result<Conn<M::Connection>, RunError<E>> {
+    /// Takes a validated idle connection, or queues up as a waiter with the
+    /// given priority if there are none and `queue` is set.
+    async fn take_idle_conn<E>(
+        &self,
+        queue: bool,
+        priority: Priority,
+    ) -> Result<Checkout<M>, RunError<E>> {
         let inner = self.inner.clone();
 
         loop {
-            let mut internals = inner.internals.lock().await;
-            if let Some(conn) = internals.conns.pop_front() {
-                // Spin up a new connection if necessary to retain our minimum idle count
-                if internals.num_conns + internals.pending_conns < inner.statics.max_size {
-                    Pool {
-                        inner: inner.clone(),
+            let conn = {
+                let mut internals = inner.lock();
+                if internals.closed {
+                    return Err(RunError::PoolClosed);
+                }
+
+                match internals.conns.pop_front() {
+                    Some(conn) => {
+                        // Spin up a new connection if necessary to retain our minimum idle count
+                        if internals.num_conns + internals.pending_conns < internals.limits.max_size
+                        {
+                            Pool {
+                                inner: inner.clone(),
+                            }
+                            .spawn_replenishing();
+                        }
+                        conn
+                    }
+                    None => {
+                        // Queue up while still holding the lock, so that a connection
+                        // returned in the meantime can't slip past us into the idle queue.
+                        let checkout = if queue {
+                            if let Some(max_waiters) = inner.statics.max_waiters {
+                                if internals.waiters.len() >= max_waiters {
+                                    // Callers that gave up may still be queued.
+                                    internals.waiters.retain(|w| !w.tx.is_canceled());
+                                }
+                                if internals.waiters.len() >= max_waiters {
+                                    AtomicStatistics::record(&inner.statistics.get_rejected, 1);
+                                    return Err(RunError::TooManyWaiters);
+                                }
+                            }
+                            let (tx, rx) = oneshot::channel();
+                            internals.waiters.push_back(Waiter {
+                                tx,
+                                priority,
+                                since: Instant::now(),
+                            });
+                            Checkout::Wait(rx)
+                        } else {
+                            Checkout::Empty
+                        };
+                        if internals.num_conns + internals.pending_conns < internals.limits.max_size
+                        {
+                            spawn_add_connection(&inner);
+                        }
+                        return Ok(checkout);
                     }
-                    .spawn_replenishing();
                 }
+            };
+
+            if !inner.statics.test_on_check_out
+                || conn.idle_start.elapsed() < inner.statics.validation_threshold
+            {
+                return Ok(Checkout::Idle(conn.conn));
+            }
 
-                if inner.statics.test_on_check_out {
-                    let (mut conn, birth) = (conn.conn.conn, conn.conn.birth);
+            // Validate without holding the lock, so that a slow check doesn't
+            // hold up every other user of the pool.
+            let mut conn = conn.conn;
+            let slot = SlotGuard::new(self);
+            let res = inner.manager.is_valid(&mut conn.conn).await;
+            slot.disarm();
+            match res {
+                Ok(()) => return Ok(Checkout::Idle(conn)),
+                Err(_) => {
+                    AtomicStatistics::record(&inner.statistics.connections_closed_invalid, 1);
+                    self.close_conn(conn.conn);
+                }
+            }
+        }
+    }
 
-                    match inner.manager.is_valid(&mut conn).await {
-                        Ok(()) => return Ok(Conn { conn, birth }),
-                        Err(_) => {
-                            mem::drop(conn);
-                            drop_connections(&inner, &mut internals, 1);
+    /// Gets a connection, waiting until `deadline` for one to become available.
+    ///
+    /// If `deadline` is `None`, this fails immediately if there are no idle
+    /// connections.
+    async fn get_conn<E>(
+        &self,
+        deadline: Option<Instant>,
+        priority: Priority,
+    ) -> Result<Conn<M::Connection>, RunError<E>>
+    where
+        E: From<M::Error>,
+    {
+        let inner = self.inner.clone();
+        let start = Instant::now();
+        let mut waited = false;
+
+        loop {
+            let mut conn = match self.take_idle_conn(deadline.is_some(), priority).await? {
+                Checkout::Idle(conn) => conn,
+                Checkout::Empty => return Err(RunError::NoIdleConnections),
+                Checkout::Wait(rx) => {
+                    waited = true;
+                    let deadline = deadline.unwrap();
+                    let mut wait = WaitGuard { pool: self, rx };
+                    match inner.or_timeout(deadline, &mut wait.rx).await {
+                        Ok(Some(Ok(conn))) => conn,
+                        // Establishing a connection failed in a way that retrying won't fix.
+                        Ok(Some(Err(e))) => return Err(RunError::User(e.into())),
+                        // Waiters are only ever dropped when the pool is closed.
+                        Err(oneshot::Canceled) => return Err(RunError::PoolClosed),
+                        Ok(None) => {
+                            inner.statistics.record_wait(start.elapsed());
+                            AtomicStatistics::record(&inner.statistics.get_timed_out, 1);
+                            // Forget about our waiter, so that it isn't reported in `State`.
+                            wait.rx.close();
+                            let mut internals = inner.lock();
+                            let err = internals.timed_out(start.elapsed());
+                            internals.waiters.retain(|w| !w.tx.is_canceled());
+                            return Err(err);
                         }
                     }
+                }
+            };
+
+            if let Some(customizer) = &inner.statics.connection_customizer {
+                let slot = SlotGuard::new(self);
+                let res = customizer.on_acquire(&mut conn.conn).await;
+                slot.disarm();
+                if let Err(e) = res {
+                    inner.statics.error_sink.sink(e);
+                    self.close_conn(conn.conn);
                     continue;
-                } else {
-                    return Ok(conn.conn);
                 }
-            } else {
-                break;
             }
-        }
 
-        let (tx, rx) = oneshot::channel();
-        {
-            let mut locked = inner.internals.lock().await;
-            locked.waiters.push_back(tx);
-            if locked.num_conns + locked.pending_conns < inner.statics.max_size {
-                let inner = inner.clone();
-                spawn(async move {
-                    let f = add_connection(inner.clone());
-                    inner.sink_error(f).map(|_| ()).await;
-                });
+            if waited {
+                inner.statistics.record_wait(start.elapsed());
+                AtomicStatistics::record(&inner.statistics.get_waited, 1);
+            } else {
+                AtomicStatistics::record(&inner.statistics.get_direct, 1);
             }
+            conn.uses += 1;
+            return Ok(conn);
         }
+    }
 
-        match inner.or_timeout(rx).await {
-            Ok(Some(conn)) => Ok(conn),
-            _ => Err(RunError::TimedOut),
-        }
+    /// Checks out a connection, keeping track of where it was checked out if
+    /// leak detection is enabled.
+    async fn checkout<'a>(
+        pool: Cow<'a, Pool<M>>,
+        deadline: Option<Instant>,
+        priority: Priority,
+        location: &'static Location<'static>,
+    ) -> Result<PooledConnection<'a, M>, RunError<M::Error>> {
+        let conn = pool.get_conn::<M::Error>(deadline, priority).await?;
+        let lease = pool.start_lease(location);
+        Ok(PooledConnection {
+            pool,
+            conn: Some(conn),
+            broken: false,
+            lease,
+        })
+    }
+
+    fn start_lease(&self, location: &'static Location<'static>) -> Option<u64> {
+        self.inner.statics.leak_detection_threshold?;
+        let mut internals = self.inner.lock();
+        let id = internals.next_lease;
+        internals.next_lease += 1;
+        internals.leases.insert(
+            id,
+            Lease {
+                location,
+                since: Instant::now(),
+                reported: false,
+            },
+        );
+        Some(id)
+    }
+
+    fn end_lease(&self, id: u64) {
+        self.inner.lock().leases.remove(&id);
     }
 
     /// Retrieves a connection from the pool.
-    pub async fn get(&self) -> Result<PooledConnection<'_, M>, RunError<M::Error>> {
-        self.get_conn::<M::Error>()
-            .map(move |res| {
-                res.map(|conn| PooledConnection {
-                    pool: self,
-                    checkout: Instant::now(),
-                    conn: Some(conn),
-                })
-            })
+    ///
+    /// Waits for at most `Builder::checkout_timeout` before giving up.
+    #[track_caller]
+    pub fn get(
+        &self,
+    ) -> impl Future<Output = Result<PooledConnection<'_, M>, RunError<M::Error>>> + '_ {
+        let location = Location::caller();
+        async move {
+            let deadline = Instant::now() + self.inner.statics.checkout_timeout;
+            Self::checkout(
+                Cow::Borrowed(self),
+                Some(deadline),
+                Priority::Normal,
+                location,
+            )
             .await
+        }
+    }
+
+    /// Retrieves a connection from the pool, waiting for at most `timeout`.
+    #[track_caller]
+    pub fn get_timeout(
+        &self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<PooledConnection<'_, M>, RunError<M::Error>>> + '_ {
+        let location = Location::caller();
+        async move {
+            let deadline = Instant::now() + timeout;
+            Self::checkout(
+                Cow::Borrowed(self),
+                Some(deadline),
+                Priority::Normal,
+                location,
+            )
+            .await
+        }
+    }
+
+    /// Retrieves a connection from the pool, waiting until `deadline` at the latest.
+    #[track_caller]
+    pub fn get_deadline(
+        &self,
+        deadline: Instant,
+    ) -> impl Future<Output = Result<PooledConnection<'_, M>, RunError<M::Error>>> + '_ {
+        let location = Location::caller();
+        Self::checkout(
+            Cow::Borrowed(self),
+            Some(deadline),
+            Priority::Normal,
+            location,
+        )
+    }
+
+    /// Retrieves a connection from the pool with the given priority.
+    ///
+    /// If the caller has to wait for a connection, it is served before callers
+    /// of a lower priority, subject to `Builder::priority_aging`.
+    #[track_caller]
+    pub fn get_with_priority(
+        &self,
+        priority: Priority,
+    ) -> impl Future<Output = Result<PooledConnection<'_, M>, RunError<M::Error>>> + '_ {
+        let location = Location::caller();
+        async move {
+            let deadline = Instant::now() + self.inner.statics.checkout_timeout;
+            Self::checkout(Cow::Borrowed(self), Some(deadline), priority, location).await
+        }
+    }
+
+    /// Retrieves an idle connection from the pool without waiting.
+    ///
+    /// If there are no idle connections, this fails with
+    /// `RunError::NoIdleConnections` instead of queueing up for one. The idle
+    /// connection is still validated if `test_on_check_out` is set.
+    #[track_caller]
+    pub fn try_get(
+        &self,
+    ) -> impl Future<Output = Result<PooledConnection<'_, M>, RunError<M::Error>>> + '_ {
+        let location = Location::caller();
+        Self::checkout(Cow::Borrowed(self), None, Priority::Normal, location)
+    }
+
+    /// Retrieves an owned connection from the pool.
+    ///
+    /// Unlike `get`, the returned connection holds its own handle to the pool,
+    /// so it can be moved into spawned tasks or stored in other structures.
+    #[track_caller]
+    pub fn get_owned(
+        &self,
+    ) -> impl Future<Output = Result<PooledConnection<'static, M>, RunError<M::Error>>> {
+        let location = Location::caller();
+        let pool = self.clone();
+        async move {
+            let deadline = Instant::now() + pool.inner.statics.checkout_timeout;
+            Self::checkout(Cow::Owned(pool), Some(deadline), Priority::Normal, location).await
+        }
     }
 
     /// Get a new dedicated connection that will not be managed by the pool.
@@ -760,9 +2537,38 @@ pub struct PooledConnection<'a, M>
 where
     M: ManageConnection,
 {
-    pool: &'a Pool<M>,
-    checkout: Instant,
+    pool: Cow<'a, Pool<M>>,
     conn: Option<Conn<M::Connection>>,
+    broken: bool,
+    lease: Option<u64>,
+}
+
+impl<'a, M> PooledConnection<'a, M>
+where
+    M: ManageConnection,
+{
+    /// Marks the connection as broken, so that it is closed instead of being
+    /// returned to the pool.
+    ///
+    /// This is useful when the caller knows that a connection can't be used
+    /// anymore, e.g. after a protocol error, but `ManageConnection::has_broken`
+    /// can't tell.
+    pub fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+
+    /// Takes the connection out of the pool for good.
+    ///
+    /// The pool no longer counts the connection towards `max_size`, and opens
+    /// a new one in its place if necessary.
+    pub fn detach(mut self) -> M::Connection {
+        if let Some(id) = self.lease.take() {
+            self.pool.end_lease(id);
+        }
+        let conn = self.conn.take().unwrap();
+        self.pool.forget_conn();
+        conn.conn
+    }
 }
 
 impl<'a, M> Deref for PooledConnection<'a, M>
@@ -800,10 +2606,14 @@ where
     M: ManageConnection,
 {
     fn drop(&mut self) {
-        futures::executor::block_on(async {
-            self.pool
-                .put_back(self.checkout, self.conn.take().unwrap().conn)
-                .await;
-        })
+        if let Some(id) = self.lease.take() {
+            self.pool.end_lease(id);
+        }
+        match self.conn.take() {
+            Some(conn) if self.broken => self.pool.discard_conn(conn),
+            Some(conn) => self.pool.put_back(conn),
+            // The connection was detached.
+            None => {}
+        }
     }
 }
@@ -269,7 +269,7 @@ async fn test_initialization_failure() {
         .min_idle(Some(1))
         .build(manager)
         .await;
-    assert_eq!(res.unwrap_err(), Error);
+    assert_eq!(res.unwrap_err(), RunError::User(Error));
 }
 
 #[tokio::test]
@@ -285,7 +285,7 @@ async fn test_lazy_initialization_failure() {
             ready(r)
         })
         .await;
-    assert_eq!(res.unwrap_err(), bb8::RunError::TimedOut);
+    assert!(matches!(res.unwrap_err(), bb8::RunError::TimedOut(_)));
 }
 
 #[tokio::test]
@@ -777,3 +777,1218 @@ async fn test_guard() {
     tx4.send(()).unwrap();
     tx6.send(()).unwrap();
 }
+
+#[tokio::test]
+async fn test_get_owned() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get_owned().await.unwrap();
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let _conn = conn;
+        rx.await.unwrap();
+    });
+
+    let state = pool.state();
+    assert_eq!(1, state.connections);
+    assert_eq!(0, state.idle_connections);
+
+    tx.send(()).unwrap();
+    let conn = pool.get().await.unwrap();
+    drop(conn);
+    assert_eq!(1, pool.state().idle_connections);
+}
+
+#[tokio::test]
+async fn test_drop_returns_without_runtime() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(1))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get_owned().await.unwrap();
+    let waiter = {
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.get().await.map(|_| ()) })
+    };
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+
+    // Returning a connection must not need to wait on the executor.
+    std::thread::spawn(move || drop(conn)).join().unwrap();
+
+    waiter.await.unwrap().unwrap();
+    assert_eq!(1, pool.state().idle_connections);
+}
+
+#[tokio::test]
+async fn test_state_waiters() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get().await.unwrap();
+    let clone = pool.clone();
+    let waiter = tokio::spawn(async move { clone.get().await.map(|_| ()) });
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+
+    let state = pool.state();
+    assert_eq!(1, state.connections);
+    assert_eq!(0, state.idle_connections);
+    assert_eq!(0, state.pending_connections);
+    assert_eq!(1, state.waiters);
+
+    assert!(waiter.await.unwrap().is_err());
+    assert_eq!(0, pool.state().waiters);
+    drop(conn);
+}
+
+#[tokio::test]
+async fn test_statistics() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .connection_timeout(Duration::from_millis(100))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get().await.unwrap();
+    assert!(pool.get().await.is_err());
+    drop(conn);
+    drop(pool.get().await.unwrap());
+
+    let stats = pool.statistics();
+    assert_eq!(2, stats.get_direct);
+    assert_eq!(0, stats.get_waited);
+    assert_eq!(1, stats.get_timed_out);
+    assert!(stats.get_wait_time >= Duration::from_millis(100));
+    assert_eq!(1, stats.connections_created);
+    assert_eq!(0, stats.connections_failed);
+    assert_eq!(0, stats.connections_closed_broken);
+    assert_eq!(0, stats.connections_closed_invalid);
+}
+
+#[tokio::test]
+async fn test_connection_customizer() {
+    static CONNECTS: AtomicUsize = AtomicUsize::new(0);
+    static ACQUIRES: AtomicUsize = AtomicUsize::new(0);
+    static RELEASES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Customizer;
+
+    #[async_trait]
+    impl CustomizeConnection<FakeConnection, Error> for Customizer {
+        async fn on_connect(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            CONNECTS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn on_acquire(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            // Reject the very first checkout.
+            match ACQUIRES.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(Error),
+                _ => Ok(()),
+            }
+        }
+
+        async fn on_release(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            RELEASES.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(Customizer))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    pool.run(|conn| async { Ok::<_, (Error, _)>(((), conn)) })
+        .await
+        .unwrap();
+
+    assert_eq!(CONNECTS.load(Ordering::SeqCst), 2);
+    assert_eq!(ACQUIRES.load(Ordering::SeqCst), 2);
+    assert_eq!(RELEASES.load(Ordering::SeqCst), 1);
+    assert_eq!(1, pool.state().idle_connections);
+
+    // Releasing still works when the connection is dropped outside the runtime.
+    let conn = pool.get_owned().await.unwrap();
+    std::thread::spawn(move || drop(conn)).join().unwrap();
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert_eq!(RELEASES.load(Ordering::SeqCst), 2);
+    assert_eq!(1, pool.state().idle_connections);
+}
+
+#[tokio::test]
+async fn test_close() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(2))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get_owned().await.unwrap();
+    let clone = pool.clone();
+    let waiter = tokio::spawn(async move {
+        let _conn = clone.get().await.unwrap();
+        clone.get().await.map(|_| ())
+    });
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(1, pool.state().waiters);
+
+    // The connection is still checked out, so closing can't finish yet.
+    let res = pool.close(Some(Duration::from_millis(50))).await;
+    assert!(matches!(res, Err(RunError::TimedOut(_))));
+    assert!(matches!(waiter.await.unwrap(), Err(RunError::PoolClosed)));
+    assert!(matches!(pool.get().await, Err(RunError::PoolClosed)));
+
+    let state = pool.state();
+    assert_eq!(1, state.connections);
+    assert_eq!(0, state.idle_connections);
+    assert_eq!(0, state.waiters);
+
+    let clone = pool.clone();
+    let closing = tokio::spawn(async move { clone.close(None).await });
+    drop(conn);
+    closing.await.unwrap().unwrap();
+    assert_eq!(0, pool.state().connections);
+}
+
+#[tokio::test]
+async fn test_close_while_connecting() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .backoff(Box::new(ExponentialBackoff::new(Duration::from_secs(5))))
+        .build_unchecked(NthConnectionFailManager::<FakeConnection>::new(0));
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(1, pool.state().pending_connections);
+
+    // Closing doesn't wait for the attempt to back off, nor for it to run out
+    // of budget.
+    pool.close(Some(Duration::from_millis(50))).await.unwrap();
+    assert_eq!(0, pool.state().pending_connections);
+
+    // Nor does it wait for an attempt that hangs.
+    struct Hang;
+
+    #[async_trait]
+    impl ManageConnection for Hang {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            pending().await
+        }
+
+        async fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .build_unchecked(Hang);
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(1, pool.state().pending_connections);
+
+    pool.close(Some(Duration::from_millis(50))).await.unwrap();
+    assert_eq!(0, pool.state().pending_connections);
+}
+
+#[tokio::test]
+async fn test_resize() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(1))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get_owned().await.unwrap();
+    let clone = pool.clone();
+    let waiter = tokio::spawn(async move { clone.get_owned().await });
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(1, pool.state().waiters);
+
+    // Growing the pool serves the waiter with a new connection.
+    pool.set_max_size(3);
+    let other = waiter.await.unwrap().unwrap();
+    assert_eq!(2, pool.state().connections);
+
+    pool.set_min_idle(Some(1));
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(3, pool.state().connections);
+    assert_eq!(1, pool.state().idle_connections);
+
+    // Shrinking closes the idle connection right away, and the extra checked
+    // out connection once it is returned.
+    pool.set_min_idle(None);
+    pool.set_max_size(1);
+    let state = pool.state();
+    assert_eq!(2, state.connections);
+    assert_eq!(0, state.idle_connections);
+
+    drop(conn);
+    drop(other);
+    let state = pool.state();
+    assert_eq!(1, state.connections);
+    assert_eq!(1, state.idle_connections);
+}
+
+#[tokio::test]
+async fn test_resize_invalid() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(2))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let clone = pool.clone();
+    assert!(tokio::spawn(async move { clone.set_max_size(1) })
+        .await
+        .is_err());
+    let clone = pool.clone();
+    assert!(tokio::spawn(async move { clone.set_min_idle(Some(3)) })
+        .await
+        .is_err());
+
+    // The failed calls leave the pool usable.
+    drop(pool.get().await.unwrap());
+    assert_eq!(2, pool.state().idle_connections);
+}
+
+#[tokio::test]
+async fn test_get_with_timeouts() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .connection_timeout(Duration::from_secs(30))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.try_get().await.unwrap();
+    assert!(matches!(
+        pool.try_get().await,
+        Err(RunError::NoIdleConnections)
+    ));
+    assert_eq!(0, pool.statistics().get_timed_out);
+    assert_eq!(0, pool.state().waiters);
+
+    let start = std::time::Instant::now();
+    assert!(matches!(
+        pool.get_timeout(Duration::from_millis(50)).await,
+        Err(RunError::TimedOut(_))
+    ));
+    let deadline = std::time::Instant::now() + Duration::from_millis(50);
+    assert!(matches!(
+        pool.get_deadline(deadline).await,
+        Err(RunError::TimedOut(_))
+    ));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    drop(conn);
+    pool.get_timeout(Duration::from_millis(50)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_connect_timeout() {
+    static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Handler;
+
+    #[async_trait]
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            // Hang on the first attempt, like a connect to a dead host.
+            if ATTEMPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+                pending::<()>().await;
+            }
+            Ok(FakeConnection)
+        }
+
+        async fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .checkout_timeout(Duration::from_secs(5))
+        .connect_timeout(Some(Duration::from_millis(100)))
+        .build(Handler)
+        .await
+        .unwrap();
+
+    pool.get().await.unwrap();
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 2);
+    assert_eq!(1, pool.statistics().connections_failed);
+
+    // Without any error to report, giving up is reported as a timeout.
+    struct Hang;
+
+    #[async_trait]
+    impl ManageConnection for Hang {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            pending().await
+        }
+
+        async fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+    }
+
+    let res = Pool::builder()
+        .min_idle(Some(1))
+        .connect_timeout(Some(Duration::from_millis(50)))
+        .connect_retry_budget(Duration::from_millis(200))
+        .build(Hang)
+        .await;
+    assert!(matches!(res.unwrap_err(), RunError::TimedOut(_)));
+
+    // Rather than backing off past the end of the budget, just to make an
+    // attempt that can't succeed in no time at all, give up right away.
+    let pool = Pool::builder()
+        .min_idle(Some(1))
+        .connect_retry_budget(Duration::from_millis(100))
+        .backoff(Box::new(ExponentialBackoff::new(Duration::from_millis(30))))
+        .build_unchecked(NthConnectionFailManager::<FakeConnection>::new(0));
+    tokio::time::delay_for(Duration::from_millis(80)).await;
+    assert_eq!(0, pool.state().pending_connections);
+    assert_eq!(2, pool.statistics().connections_failed);
+}
+
+#[tokio::test]
+async fn test_backoff() {
+    #[derive(Debug)]
+    struct Fixed;
+
+    impl Backoff for Fixed {
+        fn next_delay(&self, _attempts: u32) -> Option<Duration> {
+            Some(Duration::from_millis(10))
+        }
+    }
+
+    let backoff = ExponentialBackoff::new(Duration::from_millis(1))
+        .jitter(true)
+        .max_attempts(Some(3));
+    for attempts in 1..3 {
+        assert!(backoff.next_delay(attempts).unwrap() <= Duration::from_millis(2 << attempts));
+    }
+    assert_eq!(backoff.next_delay(3), None);
+
+    let start = std::time::Instant::now();
+    let pool = Pool::builder()
+        .min_idle(Some(1))
+        .backoff(Box::new(backoff))
+        .build(NthConnectionFailManager::<FakeConnection>::new(0))
+        .await;
+    assert_eq!(pool.unwrap_err(), RunError::User(Error));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .backoff(Box::new(Fixed))
+        .build_unchecked(NthConnectionFailManager::<FakeConnection>::new(0));
+    assert!(matches!(
+        pool.get().await.unwrap_err(),
+        RunError::TimedOut(_)
+    ));
+    assert!(pool.statistics().connections_failed > 5);
+}
+
+#[tokio::test]
+async fn test_non_retryable_error() {
+    static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Handler;
+
+    #[async_trait]
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+            Err(Error)
+        }
+
+        async fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+
+        fn is_retryable(&self, _: &Self::Error) -> bool {
+            false
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let res = Pool::builder()
+        .min_idle(Some(1))
+        .max_size(1)
+        .build(Handler)
+        .await;
+    assert_eq!(res.unwrap_err(), RunError::User(Error));
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 1);
+
+    // Waiting callers get the error too, instead of timing out.
+    let pool = Pool::builder().max_size(1).build_unchecked(Handler);
+    assert_eq!(pool.get().await.unwrap_err(), RunError::User(Error));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // All of them, not just the first one.
+    let start = std::time::Instant::now();
+    let results = future::join_all((0..3).map(|_| pool.get())).await;
+    for res in results {
+        assert_eq!(res.unwrap_err(), RunError::User(Error));
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_timeout_diagnostics() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .checkout_timeout(Duration::from_millis(500))
+        .build_unchecked(NthConnectionFailManager::<FakeConnection>::new(0));
+
+    match pool.get().await.unwrap_err() {
+        RunError::TimedOut(diagnostics) => {
+            assert!(diagnostics.waited >= Duration::from_millis(500));
+            assert_eq!(0, diagnostics.state.connections);
+            assert_eq!(1, diagnostics.state.pending_connections);
+            assert_eq!(1, diagnostics.state.waiters);
+            assert_eq!(Some("blammo".to_owned()), diagnostics.last_connect_error);
+
+            let msg = RunError::<Error>::TimedOut(diagnostics).to_string();
+            assert!(msg.contains("last connect error: blammo"), "{}", msg);
+        }
+        e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn test_priority() {
+    async fn order(pool: &Pool<OkManager<FakeConnection>>, head_start: Duration) -> Vec<Priority> {
+        let conn = pool.get().await.unwrap();
+        let served = std::sync::Arc::new(Mutex::new(Vec::new()));
+
+        let mut waiters = Vec::new();
+        for &priority in &[Priority::Low, Priority::High] {
+            let (pool, served) = (pool.clone(), served.clone());
+            waiters.push(tokio::spawn(async move {
+                let _conn = pool.get_with_priority(priority).await.unwrap();
+                served.lock().unwrap().push(priority);
+            }));
+            tokio::time::delay_for(head_start).await;
+        }
+
+        assert_eq!(2, pool.state().waiters);
+        drop(conn);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        let served = served.lock().unwrap().clone();
+        served
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .priority_aging(Duration::from_millis(200))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    // The high priority waiter overtakes the low priority one...
+    assert_eq!(
+        vec![Priority::High, Priority::Low],
+        order(&pool, Duration::from_millis(10)).await
+    );
+    // ...unless the low priority one has been waiting long enough.
+    assert_eq!(
+        vec![Priority::Low, Priority::High],
+        order(&pool, Duration::from_millis(500)).await
+    );
+}
+
+#[tokio::test]
+async fn test_max_waiters() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .max_waiters(Some(1))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    let conn = pool.get().await.unwrap();
+
+    // The first waiter queues up, the second is turned away.
+    let waiter = {
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.get().await.map(drop) })
+    };
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert_eq!(1, pool.state().waiters);
+    assert_eq!(RunError::TooManyWaiters, pool.get().await.unwrap_err());
+    assert_eq!(1, pool.statistics().get_rejected);
+
+    drop(conn);
+    waiter.await.unwrap().unwrap();
+
+    // Waiters that gave up don't count towards the limit.
+    let conn = pool.get().await.unwrap();
+    assert!(pool.get_timeout(Duration::from_millis(10)).await.is_err());
+    assert!(pool.get_timeout(Duration::from_millis(10)).await.is_err());
+    assert_eq!(1, pool.statistics().get_rejected);
+    drop(conn);
+}
+
+#[tokio::test]
+async fn test_queue_strategy() {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Connection(usize);
+
+    impl Default for Connection {
+        fn default() -> Self {
+            Connection(NEXT_ID.fetch_add(1, Ordering::SeqCst))
+        }
+    }
+
+    async fn used_ids(pool: &Pool<OkManager<Connection>>) -> Vec<usize> {
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(pool.get().await.unwrap().0);
+        }
+        ids
+    }
+
+    let build = |strategy| {
+        Pool::builder()
+            .max_size(3)
+            .min_idle(Some(3))
+            .queue_strategy(strategy)
+            .build(OkManager::<Connection>::new())
+    };
+
+    // FIFO cycles through every idle connection...
+    let pool = build(QueueStrategy::Fifo).await.unwrap();
+    let ids = used_ids(&pool).await;
+    assert_ne!(ids[0], ids[1]);
+    assert_ne!(ids[1], ids[2]);
+
+    // ...while LIFO keeps reusing the same one.
+    let pool = build(QueueStrategy::Lifo).await.unwrap();
+    let ids = used_ids(&pool).await;
+    assert_eq!(ids[0], ids[1]);
+    assert_eq!(ids[1], ids[2]);
+
+    // With LIFO, the connections that go unused age out.
+    let pool = Pool::builder()
+        .max_size(3)
+        .queue_strategy(QueueStrategy::Lifo)
+        .idle_timeout(Some(Duration::from_millis(500)))
+        .reaper_rate(Duration::from_millis(100))
+        .build(OkManager::<Connection>::new())
+        .await
+        .unwrap();
+    let conns = try_join_all((0..3).map(|_| pool.get())).await.unwrap();
+    drop(conns);
+    assert_eq!(3, pool.state().idle_connections);
+
+    for _ in 0..10 {
+        drop(pool.get().await.unwrap());
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    assert_eq!(1, pool.state().connections);
+    assert_eq!(2, pool.statistics().connections_closed_idle_timeout);
+}
+
+#[tokio::test]
+async fn test_idle_health_check() {
+    static FAILURES: AtomicUsize = AtomicUsize::new(0);
+    static HANGS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Handler;
+
+    #[async_trait]
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            Ok(FakeConnection)
+        }
+
+        async fn is_valid(&self, _: &mut Self::Connection) -> Result<(), Self::Error> {
+            if HANGS
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                pending::<()>().await;
+            }
+            match FAILURES.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)) {
+                Ok(_) => Err(Error),
+                Err(_) => Ok(()),
+            }
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(2))
+        .test_on_check_out(false)
+        .idle_health_check_interval(Some(Duration::from_millis(100)))
+        .idle_health_check_timeout(Duration::from_millis(50))
+        .build(Handler)
+        .await
+        .unwrap();
+    assert_eq!(2, pool.state().idle_connections);
+
+    // Both idle connections fail their next check and get replaced.
+    FAILURES.store(2, Ordering::SeqCst);
+    tokio::time::delay_for(Duration::from_millis(350)).await;
+    assert_eq!(0, FAILURES.load(Ordering::SeqCst));
+
+    let stats = pool.statistics();
+    assert_eq!(2, stats.connections_closed_invalid);
+    assert_eq!(4, stats.connections_created);
+    let state = pool.state();
+    assert_eq!(2, state.connections);
+    assert_eq!(2, state.idle_connections);
+
+    // So do connections whose check hangs, but not before the other
+    // connection has been handed out in the meantime.
+    HANGS.store(2, Ordering::SeqCst);
+    timeout(Duration::from_secs(1), async {
+        while HANGS.load(Ordering::SeqCst) == 2 {
+            tokio::time::delay_for(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .unwrap();
+    drop(pool.get_timeout(Duration::from_millis(20)).await.unwrap());
+    tokio::time::delay_for(Duration::from_millis(400)).await;
+    assert_eq!(0, HANGS.load(Ordering::SeqCst));
+
+    let stats = pool.statistics();
+    assert_eq!(4, stats.connections_closed_invalid);
+    assert_eq!(6, stats.connections_created);
+    let state = pool.state();
+    assert_eq!(2, state.connections);
+    assert_eq!(2, state.idle_connections);
+}
+
+#[tokio::test]
+async fn test_validation_threshold() {
+    static VALIDATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Handler;
+
+    #[async_trait]
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            Ok(FakeConnection)
+        }
+
+        async fn is_valid(&self, _: &mut Self::Connection) -> Result<(), Self::Error> {
+            VALIDATIONS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .validation_threshold(Duration::from_millis(100))
+        .build(Handler)
+        .await
+        .unwrap();
+
+    // Recently used connections are handed out as is...
+    drop(pool.get().await.unwrap());
+    drop(pool.get().await.unwrap());
+    assert_eq!(0, VALIDATIONS.load(Ordering::SeqCst));
+
+    // ...but those which sat idle for a while are validated first.
+    tokio::time::delay_for(Duration::from_millis(150)).await;
+    drop(pool.get().await.unwrap());
+    assert_eq!(1, VALIDATIONS.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_recycle() {
+    static RECYCLED: AtomicUsize = AtomicUsize::new(0);
+    static FAIL: AtomicBool = AtomicBool::new(false);
+
+    struct Handler;
+
+    #[async_trait]
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            Ok(FakeConnection)
+        }
+
+        async fn is_valid(&self, _: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            false
+        }
+
+        async fn recycle(&self, _: &mut Self::Connection) -> Result<(), Self::Error> {
+            RECYCLED.fetch_add(1, Ordering::SeqCst);
+            if FAIL.load(Ordering::SeqCst) {
+                Err(Error)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .test_on_check_in(true)
+        .build(Handler)
+        .await
+        .unwrap();
+
+    pool.run(|conn| async { Ok::<_, (Error, _)>(((), conn)) })
+        .await
+        .unwrap();
+    assert_eq!(1, RECYCLED.load(Ordering::SeqCst));
+    assert_eq!(1, pool.state().idle_connections);
+
+    // Connections returned by dropping them are recycled in the background.
+    FAIL.store(true, Ordering::SeqCst);
+    drop(pool.get().await.unwrap());
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert_eq!(2, RECYCLED.load(Ordering::SeqCst));
+    assert_eq!(0, pool.state().connections);
+    assert_eq!(1, pool.statistics().connections_closed_recycle);
+
+    // Even when they are dropped outside the runtime.
+    FAIL.store(false, Ordering::SeqCst);
+    let conn = pool.get_owned().await.unwrap();
+    std::thread::spawn(move || drop(conn)).join().unwrap();
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert_eq!(3, RECYCLED.load(Ordering::SeqCst));
+    assert_eq!(1, pool.state().idle_connections);
+}
+
+#[tokio::test]
+async fn test_close_hook() {
+    static CLOSED: AtomicUsize = AtomicUsize::new(0);
+    static BROKEN: AtomicBool = AtomicBool::new(false);
+    static REJECT: AtomicBool = AtomicBool::new(false);
+
+    struct Handler;
+
+    #[derive(Debug)]
+    struct Customizer;
+
+    #[async_trait]
+    impl CustomizeConnection<FakeConnection, Error> for Customizer {
+        async fn on_connect(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            match REJECT.swap(false, Ordering::SeqCst) {
+                true => Err(Error),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            Ok(FakeConnection)
+        }
+
+        async fn is_valid(&self, _: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Self::Connection) -> bool {
+            BROKEN.load(Ordering::SeqCst)
+        }
+
+        async fn close(&self, _: Self::Connection) {
+            // Say goodbye to the server.
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            CLOSED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(3)
+        .min_idle(Some(2))
+        .connection_customizer(Box::new(Customizer))
+        .backoff(Box::new(ExponentialBackoff::new(Duration::from_millis(1))))
+        .build(Handler)
+        .await
+        .unwrap();
+
+    // Connections that broke are closed in the background.
+    BROKEN.store(true, Ordering::SeqCst);
+    drop(pool.get().await.unwrap());
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    BROKEN.store(false, Ordering::SeqCst);
+    assert_eq!(1, CLOSED.load(Ordering::SeqCst));
+    assert_eq!(2, pool.state().idle_connections);
+
+    // So are new connections which `on_connect` rejected.
+    REJECT.store(true, Ordering::SeqCst);
+    let conns = (pool.get().await.unwrap(), pool.get().await.unwrap());
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(2, CLOSED.load(Ordering::SeqCst));
+    drop(conns);
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(3, pool.state().idle_connections);
+
+    // Closing the pool waits for every connection to be closed, including
+    // those which were still checked out.
+    let conn = pool.get_owned().await.unwrap();
+    let clone = pool.clone();
+    let closing = tokio::spawn(async move { clone.close(None).await });
+    tokio::time::delay_for(Duration::from_millis(20)).await;
+    drop(conn);
+    closing.await.unwrap().unwrap();
+    assert_eq!(5, CLOSED.load(Ordering::SeqCst));
+
+    // Dropping a pool closes its idle connections, too.
+    let pool = Pool::builder()
+        .min_idle(Some(2))
+        .build(Handler)
+        .await
+        .unwrap();
+    drop(pool);
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    assert_eq!(7, CLOSED.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_max_uses() {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Connection(usize);
+
+    impl Default for Connection {
+        fn default() -> Self {
+            Connection(NEXT_ID.fetch_add(1, Ordering::SeqCst))
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .max_uses(Some(2))
+        .build(OkManager::<Connection>::new())
+        .await
+        .unwrap();
+
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        ids.push(pool.get().await.unwrap().0);
+    }
+    assert_eq!(ids[0], ids[1]);
+    assert_ne!(ids[1], ids[2]);
+    assert_eq!(ids[2], ids[3]);
+    assert_eq!(2, pool.statistics().connections_closed_max_uses);
+}
+
+#[tokio::test]
+async fn test_mark_broken_and_detach() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    // A connection marked broken is replaced instead of reused.
+    let mut conn = pool.get().await.unwrap();
+    conn.mark_broken();
+    drop(conn);
+    let _ = pool.get().await.unwrap();
+    let stats = pool.statistics();
+    assert_eq!(1, stats.connections_closed_broken);
+    assert_eq!(2, stats.connections_created);
+
+    // A detached connection no longer takes up a slot in the pool.
+    let conn = pool.get().await.unwrap();
+    let _detached: FakeConnection = conn.detach();
+    let _ = pool.get().await.unwrap();
+    assert_eq!(1, pool.state().connections);
+    assert_eq!(3, pool.statistics().connections_created);
+}
+
+#[tokio::test]
+async fn test_run_cancelled_or_panicked() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(1))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    // Cancelled while the closure holds the connection.
+    let run = pool.run(|conn| async move {
+        pending::<()>().await;
+        Ok::<_, (Error, _)>(((), conn))
+    });
+    assert!(timeout(Duration::from_millis(10), run).await.is_err());
+    assert_eq!(0, pool.state().connections);
+
+    // Panicked while the closure holds the connection.
+    let clone = pool.clone();
+    let res = tokio::spawn(async move {
+        clone
+            .run(|conn: FakeConnection| async move {
+                if true {
+                    panic!("oops");
+                }
+                Ok::<_, (Error, _)>(((), conn))
+            })
+            .await
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(0, pool.state().connections);
+
+    // The slot is still usable.
+    pool.run(|conn| async { Ok::<_, (Error, _)>(((), conn)) })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_with_connection() {
+    #[derive(Debug, Default)]
+    struct Connection {
+        queries: usize,
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(OkManager::<Connection>::new())
+        .await
+        .unwrap();
+
+    let queries = pool
+        .with_connection(|conn| {
+            async move {
+                conn.queries += 1;
+                Ok::<_, Error>(conn.queries)
+            }
+            .boxed()
+        })
+        .await
+        .unwrap();
+    assert_eq!(1, queries);
+
+    // Errors propagate, and the connection is returned either way.
+    let res: Result<(), RunError<Error>> = pool
+        .with_connection(|conn| {
+            async move {
+                conn.queries += 1;
+                Err(Error)?;
+                unreachable!()
+            }
+            .boxed()
+        })
+        .await;
+    assert_eq!(RunError::User(Error), res.unwrap_err());
+    assert_eq!(2, pool.get().await.unwrap().queries);
+}
+
+#[tokio::test]
+async fn test_run_with_retry() {
+    #[derive(Debug, PartialEq, Eq)]
+    enum QueryError {
+        Failover,
+        Syntax,
+    }
+
+    impl From<Error> for QueryError {
+        fn from(_: Error) -> Self {
+            QueryError::Failover
+        }
+    }
+
+    struct Policy(ExponentialBackoff);
+
+    impl RetryPolicy<QueryError> for Policy {
+        fn is_transient(&self, error: &QueryError) -> bool {
+            *error == QueryError::Failover
+        }
+
+        fn backoff(&self) -> &dyn Backoff {
+            &self.0
+        }
+    }
+
+    let policy = Policy(ExponentialBackoff::new(Duration::from_millis(1)).max_attempts(Some(3)));
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    // Transient errors are retried on a fresh connection...
+    let attempts = std::sync::Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let res = pool
+        .run_with_retry(&policy, move |_| {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match attempt {
+                    0 | 1 => Err(QueryError::Failover),
+                    _ => Ok(attempt),
+                }
+            }
+            .boxed()
+        })
+        .await;
+    assert_eq!(Ok(2), res);
+    let stats = pool.statistics();
+    assert_eq!(2, stats.connections_closed_retried);
+    assert_eq!(0, stats.connections_closed_broken);
+
+    // ...up to the limit set by the policy...
+    attempts.store(0, Ordering::SeqCst);
+    let counter = attempts.clone();
+    let res: Result<(), _> = pool
+        .run_with_retry(&policy, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Err(QueryError::Failover) }.boxed()
+        })
+        .await;
+    assert_eq!(RunError::User(QueryError::Failover), res.unwrap_err());
+    assert_eq!(3, attempts.load(Ordering::SeqCst));
+
+    // ...while other errors are returned right away.
+    attempts.store(0, Ordering::SeqCst);
+    let counter = attempts.clone();
+    let res: Result<(), _> = pool
+        .run_with_retry(&policy, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Err(QueryError::Syntax) }.boxed()
+        })
+        .await;
+    assert_eq!(RunError::User(QueryError::Syntax), res.unwrap_err());
+    assert_eq!(1, attempts.load(Ordering::SeqCst));
+    assert_eq!(1, pool.state().idle_connections);
+}
+
+#[tokio::test]
+async fn test_leak_detection() {
+    static LEAKS: Mutex<Vec<ConnectionLeak>> = Mutex::new(Vec::new());
+
+    #[derive(Debug, Clone, Copy)]
+    struct LeakSink;
+
+    impl ErrorSink<Error> for LeakSink {
+        fn sink(&self, _: Error) {}
+
+        fn sink_leak(&self, leak: ConnectionLeak) {
+            LEAKS.lock().unwrap().push(leak);
+        }
+
+        fn boxed_clone(&self) -> Box<dyn ErrorSink<Error>> {
+            Box::new(*self)
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .leak_detection_threshold(Some(Duration::from_millis(100)))
+        .error_sink(Box::new(LeakSink))
+        .build(OkManager::<FakeConnection>::new())
+        .await
+        .unwrap();
+
+    // A connection returned in time isn't reported...
+    drop(pool.get().await.unwrap());
+
+    // ...but one held past the threshold is, once, even when it was checked
+    // out between two scans.
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    let line = line!() + 1;
+    let conn = pool.get().await.unwrap();
+    tokio::time::delay_for(Duration::from_millis(140)).await;
+    assert_eq!(1, LEAKS.lock().unwrap().len());
+    tokio::time::delay_for(Duration::from_millis(200)).await;
+    drop(conn);
+
+    let leaks = LEAKS.lock().unwrap().clone();
+    assert_eq!(1, leaks.len());
+    assert_eq!(file!(), leaks[0].location.file());
+    assert_eq!(line, leaks[0].location.line());
+    assert!(leaks[0].held_for >= Duration::from_millis(100));
+
+    // Nothing is reported once the pool is closed.
+    let _conn = pool.get().await.unwrap();
+    pool.close(Some(Duration::from_millis(0)))
+        .await
+        .unwrap_err();
+    tokio::time::delay_for(Duration::from_millis(200)).await;
+    assert_eq!(1, LEAKS.lock().unwrap().len());
+}
@@ -1,5 +1,6 @@
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
+use futures::FutureExt;
 
 // Select some static data from a Postgres DB
 //
@@ -19,32 +20,27 @@ async fn main() {
     };
 
     let _ = pool
-        .run(|connection| async {
-            if let Err(e) = connection.simple_query("BEGIN").await {
-                return Err((e, connection));
-            }
+        .with_connection(|connection| {
+            async move {
+                connection.simple_query("BEGIN").await?;
 
-            let err = match connection.prepare("SELECT 1").await {
-                Ok(select) => match connection.query_one(&select, &[]).await {
-                    Ok(row) => {
-                        println!("result: {}", row.get::<usize, i32>(0));
-                        None
-                    }
-                    Err(e) => Some(e),
-                },
-                Err(e) => Some(e),
-            };
+                let res = async {
+                    let select = connection.prepare("SELECT 1").await?;
+                    let row = connection.query_one(&select, &[]).await?;
+                    println!("result: {}", row.get::<usize, i32>(0));
+                    Ok::<_, tokio_postgres::Error>(())
+                }
+                .await;
 
-            let finalize_query = match &err {
-                None => "COMMIT",
-                Some(_) => "ROLLBACK",
-            };
+                let finalize_query = match res {
+                    Ok(()) => "COMMIT",
+                    Err(_) => "ROLLBACK",
+                };
 
-            let _ = connection.simple_query(finalize_query).await;
-            match err {
-                Some(e) => Err((e, connection)),
-                None => Ok(((), connection)),
+                let _ = connection.simple_query(finalize_query).await;
+                res
             }
+            .boxed()
         })
         .await
         .map_err(|e| panic!("{:?}", e));
@@ -7,6 +7,7 @@ pub use tokio_postgres;
 use async_trait::async_trait;
 use futures::prelude::*;
 use tokio_postgres::config::Config;
+use tokio_postgres::error::SqlState;
 use tokio_postgres::tls::{MakeTlsConnect, TlsConnect};
 use tokio_postgres::{Client, Error, Socket};
 
@@ -77,6 +78,19 @@ where
     fn has_broken(&self, conn: &mut Self::Connection) -> bool {
         conn.is_closed()
     }
+
+    fn is_retryable(&self, error: &Self::Error) -> bool {
+        // Bad credentials or a missing database won't fix themselves.
+        match error.code() {
+            Some(code) => ![
+                SqlState::INVALID_PASSWORD,
+                SqlState::INVALID_AUTHORIZATION_SPECIFICATION,
+                SqlState::INVALID_CATALOG_NAME,
+            ]
+            .contains(code),
+            None => true,
+        }
+    }
 }
 
 impl<Tls> fmt::Debug for PostgresConnectionManager<Tls>